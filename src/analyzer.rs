@@ -2,15 +2,16 @@ use tower_lsp::lsp_types::{Diagnostic, Position, Range};
 use tree_sitter::{Node, Tree};
 
 use crate::{
-    ast::{NodeType, FUNCTION_NODE, LOOP_NODE},
+    ast::{is_assignment, NodeType, FUNCTION_NODE, LOOP_NODE},
     builtins::KEYWORDS,
     declarations::{Declaration, DeclarationKind, DeclarationMap},
-    diagnostic::{error, hint, warn, ErrorKind, HintKind, WarnKind},
+    diagnostic::{error, hint, lint, warn, ErrorKind, HintKind, WarnKind},
+    lint::{get_simplification, LintConfig},
     utils::*,
 };
 
-pub fn analyze(source: &[u8], tree: &Tree) -> AnalyzeResult {
-    Analyzer::new(source, tree).analyze()
+pub fn analyze(source: &[u8], tree: &Tree, lints: &LintConfig) -> AnalyzeResult {
+    Analyzer::new(source, tree, lints).analyze()
 }
 
 pub struct AnalyzeResult {
@@ -21,6 +22,7 @@ pub struct AnalyzeResult {
 struct Analyzer<'a> {
     source: &'a [u8],
     tree: &'a Tree,
+    lints: &'a LintConfig,
     diagnostics: Vec<Diagnostic>,
     declarations: DeclarationMap,
     identifiers: Vec<Identifier>, // FIXME: use symbol table
@@ -32,10 +34,11 @@ pub struct Identifier {
 }
 
 impl<'a> Analyzer<'a> {
-    fn new(source: &'a [u8], tree: &'a Tree) -> Self {
+    fn new(source: &'a [u8], tree: &'a Tree, lints: &'a LintConfig) -> Self {
         Self {
             source,
             tree,
+            lints,
             diagnostics: Vec::new(),
             declarations: DeclarationMap::new(),
             identifiers: Vec::new(),
@@ -78,6 +81,8 @@ impl<'a> Analyzer<'a> {
             _ => {}
         }
 
+        self.eval_lints(node);
+
         let mut cursor = Node::walk(node);
 
         for child in node.children(&mut cursor) {
@@ -87,7 +92,7 @@ impl<'a> Analyzer<'a> {
 
     fn handle_syntax_error(&mut self, node: &Node) {
         if node.is_error() {
-            let range = get_node_range(&node);
+            let range = get_node_range(node);
             let child = node.named_child(0);
             let error = match child {
                 Some(child) => {
@@ -104,11 +109,11 @@ impl<'a> Analyzer<'a> {
         }
 
         if node.is_missing() {
-            let range = get_node_range(&node);
+            let range = get_node_range(node);
             let error = match NodeType::from(node) {
                 NodeType::ExprIdentifier => {
                     let parent = node.parent();
-                    let text = parent.as_ref().map(|p| p.utf8_text(&self.source).unwrap());
+                    let text = parent.as_ref().map(|p| p.utf8_text(self.source).unwrap());
                     let kind = match text {
                         Some(".") => ErrorKind::ExpectedField,
                         _ => ErrorKind::ExpectedExpr,
@@ -136,21 +141,16 @@ impl<'a> Analyzer<'a> {
                 None => false,
             };
             let unused = match node_type {
-                NodeType::ExprBinary => {
-                    let operator_node = child.child_by_field_name("operator").unwrap();
-                    let operator = operator_node.utf8_text(&self.source).unwrap();
-
-                    operator != "="
-                }
+                NodeType::ExprBinary => !is_assignment(&child),
                 NodeType::ExprUnary | NodeType::ExprLiteral | NodeType::ExprIdentifier => true,
                 _ => false,
             };
 
             if !return_value && unused {
                 self.diagnostics
-                    .push(warn(WarnKind::UnusedResult, get_node_range(&node)));
+                    .push(warn(WarnKind::UnusedResult, get_node_range(node)));
                 self.diagnostics
-                    .push(hint(HintKind::Assign, get_node_range(&node)));
+                    .push(hint(HintKind::Assign, get_node_range(node)));
             }
         }
     }
@@ -183,7 +183,7 @@ impl<'a> Analyzer<'a> {
 
     fn eval_var_decl(&mut self, node: &Node) {
         let name_node = node.child_by_field_name("name").unwrap();
-        let name = name_node.utf8_text(&self.source).unwrap();
+        let name = name_node.utf8_text(self.source).unwrap();
         let name_range = get_node_range(&name_node);
         let kind = DeclarationKind::Variable;
 
@@ -228,7 +228,7 @@ impl<'a> Analyzer<'a> {
 
     fn eval_func_decl(&mut self, node: &Node) {
         let name_node = node.child_by_field_name("name").unwrap();
-        let name = name_node.utf8_text(&self.source).unwrap();
+        let name = name_node.utf8_text(self.source).unwrap();
         let name_range = get_node_range(&name_node);
 
         if KEYWORDS.contains(&name) {
@@ -237,7 +237,7 @@ impl<'a> Analyzer<'a> {
         }
 
         let block = node.child_by_field_name("body").unwrap();
-        let (names, args_decl) = self.get_function_args(&node);
+        let (names, args_decl) = self.get_function_args(node);
         let kind = DeclarationKind::Function(names);
         let range = Range::new(
             point_to_position(node.start_position()),
@@ -264,7 +264,7 @@ impl<'a> Analyzer<'a> {
 
     fn eval_identifier(&mut self, node: &Node) {
         if !skip_identifer(node) {
-            let name = node.utf8_text(&self.source).unwrap().to_owned();
+            let name = node.utf8_text(self.source).unwrap().to_owned();
             let range = get_node_range(node);
             let data = Identifier { name, range };
 
@@ -305,7 +305,7 @@ impl<'a> Analyzer<'a> {
         let mut cursor = Node::walk(&iterator);
 
         for child in iterator.named_children(&mut cursor) {
-            let name = child.utf8_text(&self.source).unwrap();
+            let name = child.utf8_text(self.source).unwrap();
             let kind = DeclarationKind::Variable;
             let range = get_node_range(&iterator);
             let name_range = get_node_range(&child);
@@ -321,7 +321,16 @@ impl<'a> Analyzer<'a> {
 
         if body.named_child_count() == 0 {
             self.diagnostics
-                .push(hint(HintKind::EmptyMatch, get_node_range(&node)));
+                .push(hint(HintKind::EmptyMatch, get_node_range(node)));
+        }
+    }
+
+    fn eval_lints(&mut self, node: &Node) {
+        if let Some((kind, replacement)) = get_simplification(node, self.source) {
+            if let Some(severity) = self.lints.get_level(kind).severity() {
+                self.diagnostics
+                    .push(lint(kind, severity, get_node_range(node), replacement));
+            }
         }
     }
 
@@ -338,10 +347,10 @@ impl<'a> Analyzer<'a> {
                 continue;
             }
 
-            let name = arg.utf8_text(&self.source).unwrap();
+            let name = arg.utf8_text(self.source).unwrap();
             let name_range = get_node_range(&arg);
             let kind = DeclarationKind::Variable;
-            let scope = Some(get_node_range(&body));
+            let scope = Some(get_body_scope(&body));
             let decl = Declaration::new(name.to_string(), kind, range, name_range, scope, true);
 
             names.push(name.to_owned());
@@ -349,7 +358,7 @@ impl<'a> Analyzer<'a> {
         }
 
         let kind = DeclarationKind::Variable;
-        let scope = Some(get_node_range(&body));
+        let scope = Some(get_body_scope(&body));
         let decl = Declaration::new("self".to_owned(), kind, *NIL_RANGE, *NIL_RANGE, scope, true);
         declarations.push(decl);
 
@@ -389,13 +398,20 @@ impl<'a> Analyzer<'a> {
 
     fn resolve_identifiers(&mut self) {
         for ident in &self.identifiers {
-            let decl = self.declarations.get_mut(&ident);
+            let decl = self.declarations.get_mut(ident);
 
             if let Some(decl) = decl {
                 decl.used = true;
             } else {
+                let declared = self.declarations.get_declared_at(ident.range.end);
+                let candidates = declared
+                    .iter()
+                    .map(|decl| decl.name.as_str())
+                    .chain(KEYWORDS);
+                let suggestions = get_suggestions(&ident.name, candidates);
+
                 self.diagnostics.push(error(
-                    ErrorKind::Undeclared(ident.name.to_owned()),
+                    ErrorKind::Undeclared(ident.name.to_owned(), suggestions),
                     ident.range,
                 ));
             }
@@ -412,7 +428,7 @@ impl<'a> Analyzer<'a> {
     }
 }
 
-fn skip_identifer(node: &Node) -> bool {
+pub fn skip_identifer(node: &Node) -> bool {
     if node.start_position() == node.end_position() {
         return true;
     }
@@ -435,6 +451,18 @@ fn skip_identifer(node: &Node) -> bool {
     false
 }
 
+/// Returns the scope of the parameters of a function. A lambda body without
+/// braces also covers its end, where an identifier ending the body resolves.
+fn get_body_scope(body: &Node) -> Range {
+    let mut scope = get_node_range(body);
+
+    if NodeType::from(body) != NodeType::StmtBlock {
+        scope.end.character += 1;
+    }
+
+    scope
+}
+
 fn has_parent(node: &Node, parent_types: &[NodeType]) -> bool {
     let mut parent = node.parent();
 
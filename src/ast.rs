@@ -75,3 +75,88 @@ impl From<&Node<'_>> for NodeType {
 
 pub const LOOP_NODE: [NodeType; 3] = [NodeType::StmtFor, NodeType::StmtWhile, NodeType::StmtLoop];
 pub const FUNCTION_NODE: [NodeType; 2] = [NodeType::StmtFuncDecl, NodeType::ExprLambda];
+
+pub const ASSIGN_OPERATOR: [&str; 6] = ["=", "+=", "-=", "*=", "/=", "%="];
+
+pub fn is_assignment(node: &Node) -> bool {
+    NodeType::from(node) == NodeType::ExprBinary
+        && node
+            .child_by_field_name("operator")
+            .is_some_and(|operator| ASSIGN_OPERATOR.contains(&operator.kind()))
+}
+
+pub fn has_side_effects(node: &Node) -> bool {
+    if matches!(NodeType::from(node), NodeType::ExprCall | NodeType::Error) || is_assignment(node) {
+        return true;
+    }
+
+    if NodeType::from(node) == NodeType::ExprLambda {
+        return false;
+    }
+
+    let mut cursor = node.walk();
+    let result = node
+        .named_children(&mut cursor)
+        .any(|child| has_side_effects(&child));
+
+    result
+}
+
+// Mirrors the precedence table of the tree-sitter grammar
+pub const PRIMARY_PRECEDENCE: u8 = u8::MAX;
+
+pub fn get_precedence(node: &Node) -> u8 {
+    match NodeType::from(node) {
+        NodeType::ExprBinary => {
+            let operator = node.child_by_field_name("operator");
+
+            match operator.map(|operator| operator.kind()) {
+                Some("or") => 1,
+                Some("and") => 2,
+                Some("==" | "!=") => 3,
+                Some(">=" | "<=" | "<" | ">") => 4,
+                Some("to") => 5,
+                Some("+" | "-" | "%") => 6,
+                Some("*" | "/") => 7,
+                _ => 0,
+            }
+        }
+        NodeType::ExprUnary => 8,
+        NodeType::ExprCall => 9,
+        NodeType::ExprField => 10,
+        NodeType::ExprIndex => 11,
+        NodeType::ExprIf | NodeType::ExprMatch | NodeType::ExprLambda => 0,
+        _ => PRIMARY_PRECEDENCE,
+    }
+}
+
+/// Checks whether an expression needs to be wrapped in parentheses when it
+/// replaces the target node.
+pub fn needs_parentheses(value: &Node, target: &Node) -> bool {
+    let precedence = get_precedence(value);
+
+    if precedence == PRIMARY_PRECEDENCE {
+        return false;
+    }
+
+    let Some(parent) = target.parent() else {
+        return false;
+    };
+
+    match NodeType::from(&parent) {
+        NodeType::ExprBinary => {
+            let parent_precedence = get_precedence(&parent);
+
+            match parent.child_by_field_name("rhs") == Some(*target) {
+                true => precedence <= parent_precedence && !is_assignment(&parent),
+                false => precedence < parent_precedence || is_assignment(&parent),
+            }
+        }
+        NodeType::ExprUnary => precedence <= get_precedence(&parent),
+        NodeType::ExprCall => parent.child_by_field_name("func") == Some(*target) && precedence < 9,
+        NodeType::ExprField | NodeType::ExprIndex => {
+            parent.child_by_field_name("arg") == Some(*target) && precedence < 9
+        }
+        _ => false,
+    }
+}
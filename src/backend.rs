@@ -1,35 +1,799 @@
-use dashmap::DashMap;
-use tower_lsp::{jsonrpc::Result, lsp_types::*, Client, LanguageServer};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    iter,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+use dashmap::{DashMap, DashSet};
+use serde_json::{json, Value};
+use tokio::task::{self, AbortHandle, JoinSet};
+use tower_lsp::{
+    jsonrpc::{Error, Result},
+    lsp_types::*,
+    Client, LanguageServer,
+};
 
 use crate::{
-    analyzer::analyze, builtins::KEYWORDS, declarations::DeclarationKind, document::Document,
+    builtins::KEYWORDS,
+    code_action::{get_code_actions, REMOVE_UNREACHABLE},
+    config::{Project, ProjectConfig, CONFIG_FILE},
+    declarations::DeclarationKind,
+    document::{Document, PositionEncoding, Snapshot},
+    formatter::{format_document, format_on_type, format_range, FormatOptions},
+    inlay_hint::get_inlay_hints,
+    module::Exports,
+    move_item::{move_item, MoveItemParams},
+    progress::{CancelParams, Progress, ProgressTokens},
+    settings::{Settings, SettingsMap, SECTION},
+    workspace::{collect_files, is_source_file, load_exports, load_file, EXTENSION},
 };
 
+/// Delay before analyzing a changed document, restarted on every change.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+pub const CHECK_WORKSPACE: &str = "icelang.checkWorkspace";
+
 pub struct Backend {
     client: Client,
-    document_map: DashMap<String, Document>,
+    document_map: Arc<DashMap<String, Arc<Document>>>,
+    snapshot_map: Arc<DashMap<String, Arc<Snapshot>>>,
+    open_documents: Arc<DashSet<String>>,
+    workspace_folders: RwLock<Vec<PathBuf>>,
+    watch_files: AtomicBool,
+    pull_diagnostics: AtomicBool,
+    refresh_diagnostics: AtomicBool,
+    refresh_inlay_hints: AtomicBool,
+    configuration: AtomicBool,
+    watch_configuration: AtomicBool,
+    analyses: DashMap<String, AbortHandle>,
+    settings: RwLock<SettingsMap>,
+    encoding: RwLock<PositionEncoding>,
+    progress: Arc<ProgressTokens>,
 }
 
 impl Backend {
     pub fn new(client: Client) -> Self {
         Self {
             client,
-            document_map: DashMap::new(),
+            document_map: Arc::new(DashMap::new()),
+            snapshot_map: Arc::new(DashMap::new()),
+            open_documents: Arc::new(DashSet::new()),
+            workspace_folders: RwLock::new(Vec::new()),
+            watch_files: AtomicBool::new(false),
+            pull_diagnostics: AtomicBool::new(false),
+            refresh_diagnostics: AtomicBool::new(false),
+            refresh_inlay_hints: AtomicBool::new(false),
+            configuration: AtomicBool::new(false),
+            watch_configuration: AtomicBool::new(false),
+            analyses: DashMap::new(),
+            settings: RwLock::new(SettingsMap::default()),
+            encoding: RwLock::new(PositionEncoding::default()),
+            progress: Arc::new(ProgressTokens::default()),
+        }
+    }
+
+    pub async fn move_item(&self, params: MoveItemParams) -> Result<Vec<TextEdit>> {
+        let uri = params.text_document.uri;
+
+        match self.get_document(&uri) {
+            Some(document) => {
+                let position = document.decode_position(params.position);
+                let edits = move_item(&document, position, params.direction);
+
+                Ok(document.encode_edits(edits))
+            }
+            None => Ok(vec![]),
+        }
+    }
+
+    pub async fn cancel_progress(&self, params: CancelParams) {
+        self.progress.cancel(&params.token);
+    }
+
+    fn get_document(&self, uri: &Url) -> Option<Arc<Document>> {
+        self.document_map
+            .get(&uri.to_string())
+            .map(|document| document.clone())
+    }
+
+    /// Returns the last completed analysis, which may be of an older version.
+    fn get_snapshot(&self, uri: &Url) -> Option<Arc<Snapshot>> {
+        self.snapshot_map
+            .get(&uri.to_string())
+            .map(|snapshot| snapshot.clone())
+    }
+
+    /// Returns the analysis of the current version, the document is analyzed
+    /// right away if the background analysis didn't complete yet.
+    async fn get_current_snapshot(&self, uri: &Url) -> Option<Arc<Snapshot>> {
+        let document = self.get_document(uri)?;
+        let snapshot = self
+            .get_snapshot(uri)
+            .filter(|snapshot| Arc::ptr_eq(&snapshot.document, &document));
+
+        if snapshot.is_some() {
+            return snapshot;
+        }
+
+        let settings = self.get_settings(uri);
+        let snapshot_map = self.snapshot_map.clone();
+        let uri = uri.clone();
+        let snapshot = task::spawn_blocking(move || {
+            Snapshot::analyze(document, &uri, &settings, &|module| {
+                get_exports(&snapshot_map, module)
+            })
+        })
+        .await;
+
+        snapshot.ok().map(Arc::new)
+    }
+
+    /// Analyzes the document in the background once it stopped changing, the
+    /// results are dropped if a newer version arrived in the meantime.
+    fn schedule_analysis(&self, uri: Url, version: i32, delay: Duration) {
+        let key = uri.to_string();
+        let document_map = self.document_map.clone();
+        let snapshot_map = self.snapshot_map.clone();
+        let open_documents = self.open_documents.clone();
+        let client = self.client.clone();
+        let settings = self.settings.read().unwrap().clone();
+        let pull_diagnostics = self.pull_diagnostics.load(Ordering::Relaxed);
+        let refresh_diagnostics = self.refresh_diagnostics.load(Ordering::Relaxed);
+
+        let analysis = task::spawn(async move {
+            tokio::time::sleep(delay).await;
+
+            let key = uri.to_string();
+            let document = match document_map.get(&key) {
+                Some(document) if document.version == version => document.clone(),
+                _ => return,
+            };
+            let analysis = {
+                let (document, uri, snapshot_map) =
+                    (document.clone(), uri.clone(), snapshot_map.clone());
+                let settings = settings.get(&uri);
+
+                task::spawn_blocking(move || {
+                    Snapshot::analyze(document, &uri, &settings, &|module| {
+                        get_exports(&snapshot_map, module)
+                    })
+                })
+            };
+            let Ok(snapshot) = analysis.await else {
+                return;
+            };
+
+            let is_current = || {
+                document_map
+                    .get(&key)
+                    .is_some_and(|current| Arc::ptr_eq(&current, &document))
+            };
+
+            if !is_current() {
+                return;
+            }
+
+            let diagnostics = get_diagnostics(&snapshot);
+            let exports = snapshot.exports.clone();
+            let previous = snapshot_map.insert(key.clone(), Arc::new(snapshot));
+
+            // a newer version may have replaced the document in the meantime
+            if !pull_diagnostics && is_current() {
+                client
+                    .publish_diagnostics(uri.clone(), diagnostics, Some(version))
+                    .await;
+            }
+
+            // the importers only need to be checked again when the exports changed
+            if previous.is_some_and(|previous| previous.exports == exports) {
+                return;
+            }
+
+            let dependents = task::spawn_blocking(move || {
+                analyze_dependents(&document_map, &snapshot_map, &uri, &settings)
+            })
+            .await;
+
+            if let Ok(dependents) = dependents {
+                publish_dependents(
+                    &client,
+                    &open_documents,
+                    dependents,
+                    pull_diagnostics,
+                    refresh_diagnostics,
+                )
+                .await;
+            }
+        });
+
+        if let Some(previous) = self.analyses.insert(key, analysis.abort_handle()) {
+            previous.abort();
+        }
+    }
+
+    /// Collects the files of the workspace folders which aren't ignored by
+    /// their project.
+    async fn get_workspace_files(&self) -> Vec<PathBuf> {
+        let folders = self.workspace_folders.read().unwrap().clone();
+        let mut files = Vec::new();
+
+        for folder in folders {
+            collect_files(&folder, &mut files);
+        }
+
+        self.discover_projects(&files).await;
+
+        let settings = self.settings.read().unwrap();
+
+        files.retain(|path| !settings.is_ignored(path));
+        files
+    }
+
+    /// Loads the project files found by walking up from the files which
+    /// aren't known yet.
+    async fn discover_projects(&self, files: &[PathBuf]) {
+        let mut directories: Vec<_> = files.iter().filter_map(|path| path.parent()).collect();
+
+        directories.dedup();
+
+        let mut paths: Vec<_> = directories
+            .into_iter()
+            .filter_map(ProjectConfig::find)
+            .collect();
+
+        paths.sort();
+        paths.dedup();
+
+        for path in paths {
+            let known = self
+                .settings
+                .read()
+                .unwrap()
+                .projects
+                .iter()
+                .any(|project| project.path == path);
+
+            if !known {
+                self.load_project(&path).await;
+            }
+        }
+    }
+
+    /// Reads a project file again and publishes its errors, a deleted file is
+    /// forgotten.
+    async fn load_project(&self, path: &Path) {
+        let encoding = *self.encoding.read().unwrap();
+        let project = Project::load(path, encoding);
+        let diagnostics = project
+            .as_ref()
+            .map_or_else(Vec::new, |project| project.diagnostics.clone());
+
+        {
+            let mut settings = self.settings.write().unwrap();
+
+            settings.projects.retain(|project| project.path != path);
+            settings.projects.extend(project.map(Arc::new));
+        }
+
+        if let Ok(uri) = Url::from_file_path(path) {
+            self.publish_diagnostics(uri, diagnostics).await;
+        }
+    }
+
+    /// Reads and analyzes files in parallel while reporting the progress,
+    /// returns `None` when the client cancelled the progress.
+    async fn analyze_files(
+        &self,
+        title: &str,
+        files: Vec<PathBuf>,
+    ) -> Option<Vec<(PathBuf, Snapshot)>> {
+        let encoding = *self.encoding.read().unwrap();
+        let settings = self.settings.read().unwrap().clone();
+        let mut progress = Progress::begin(&self.client, &self.progress, title, files.len()).await;
+        let snapshot_map = &self.snapshot_map;
+        let mut tasks = JoinSet::new();
+        let mut snapshots = Vec::new();
+        let mut done = 0;
+
+        for path in files {
+            let settings = settings.get_by_path(&path);
+            let snapshot_map = snapshot_map.clone();
+            let exports = move |module: &Url| get_exports(&snapshot_map, module);
+
+            tasks.spawn_blocking(move || (load_file(&path, encoding, &settings, &exports), path));
+        }
+
+        while let Some(result) = tasks.join_next().await {
+            if progress.is_cancelled() {
+                tasks.abort_all();
+                progress.end("Cancelled".to_owned()).await;
+                return None;
+            }
+
+            if let Ok((Some(snapshot), path)) = result {
+                snapshots.push((path, snapshot));
+            }
+
+            done += 1;
+            progress.report(done).await;
+        }
+
+        progress
+            .end(format!("{} files analyzed", snapshots.len()))
+            .await;
+
+        Some(snapshots)
+    }
+
+    /// Parses and analyzes the files of the workspace folders, closed files
+    /// are kept in the index for the cross-file features.
+    async fn index_workspace(&self) {
+        let files = self.get_workspace_files().await;
+        let Some(snapshots) = self.analyze_files("Indexing workspace", files).await else {
+            self.client
+                .log_message(MessageType::INFO, "workspace indexing cancelled")
+                .await;
+            return;
+        };
+        let count = snapshots.len();
+        let settings = self.settings.read().unwrap().clone();
+        let is_ignored = |key: &String| {
+            Url::parse(key)
+                .ok()
+                .and_then(|uri| uri.to_file_path().ok())
+                .is_some_and(|path| settings.is_ignored(&path))
+        };
+
+        // closed files ignored since they were indexed
+        self.snapshot_map
+            .retain(|key, _| self.open_documents.contains(key) || !is_ignored(key));
+        self.document_map
+            .retain(|key, _| self.open_documents.contains(key) || !is_ignored(key));
+
+        for (path, snapshot) in snapshots {
+            self.index_file(&path, snapshot);
+        }
+
+        self.client
+            .log_message(MessageType::INFO, format!("{} files indexed", count))
+            .await;
+        self.refresh_diagnostics().await;
+    }
+
+    /// Re-reads every closed file of the workspace and publishes their
+    /// diagnostics, the open documents already have theirs.
+    async fn check_workspace(&self) -> Option<Value> {
+        let files = self
+            .get_workspace_files()
+            .await
+            .into_iter()
+            .filter(|path| {
+                Url::from_file_path(path)
+                    .is_ok_and(|uri| !self.open_documents.contains(&uri.to_string()))
+            })
+            .collect();
+        let snapshots = self.analyze_files("Checking workspace", files).await?;
+        let count = snapshots.len();
+        let mut errors = 0;
+        let mut warnings = 0;
+
+        for (path, snapshot) in snapshots {
+            let Ok(uri) = Url::from_file_path(&path) else {
+                continue;
+            };
+            let diagnostics = get_diagnostics(&snapshot);
+
+            for diagnostic in &diagnostics {
+                match diagnostic.severity {
+                    Some(DiagnosticSeverity::ERROR) => errors += 1,
+                    Some(DiagnosticSeverity::WARNING) => warnings += 1,
+                    _ => {}
+                }
+            }
+
+            self.index_file(&path, snapshot);
+            self.publish_diagnostics(uri, diagnostics).await;
+        }
+
+        self.refresh_diagnostics().await;
+
+        Some(json!({ "files": count, "errors": errors, "warnings": warnings }))
+    }
+
+    /// Publishes the diagnostics of a file analyzed outside of the background
+    /// analysis, pulling clients get them from the next pull instead.
+    async fn publish_diagnostics(&self, uri: Url, diagnostics: Vec<Diagnostic>) {
+        let pull_diagnostics = self.pull_diagnostics.load(Ordering::Relaxed);
+
+        if !pull_diagnostics {
+            self.client
+                .publish_diagnostics(uri, diagnostics, None)
+                .await;
+        }
+    }
+
+    /// Asks pulling clients to pull the diagnostics again after closed files
+    /// changed, they would only pull on their next edit otherwise.
+    async fn refresh_diagnostics(&self) {
+        let refresh_diagnostics = self.refresh_diagnostics.load(Ordering::Relaxed);
+
+        if refresh_diagnostics {
+            if let Err(err) = self.client.workspace_diagnostic_refresh().await {
+                let message = format!("Failed to refresh the diagnostics: {}", err);
+                self.client.log_message(MessageType::ERROR, message).await;
+            }
+        }
+    }
+
+    /// Re-analyzes the files importing a module after it changed outside of
+    /// the background analysis.
+    async fn update_dependents(&self, uri: &Url) {
+        let document_map = self.document_map.clone();
+        let snapshot_map = self.snapshot_map.clone();
+        let settings = self.settings.read().unwrap().clone();
+        let pull_diagnostics = self.pull_diagnostics.load(Ordering::Relaxed);
+        let refresh_diagnostics = self.refresh_diagnostics.load(Ordering::Relaxed);
+        let uri = uri.clone();
+        let dependents = task::spawn_blocking(move || {
+            analyze_dependents(&document_map, &snapshot_map, &uri, &settings)
+        })
+        .await;
+
+        if let Ok(dependents) = dependents {
+            publish_dependents(
+                &self.client,
+                &self.open_documents,
+                dependents,
+                pull_diagnostics,
+                refresh_diagnostics,
+            )
+            .await;
+        }
+    }
+
+    /// Checks whether the file belongs to the index: an icelang file of a
+    /// workspace folder which isn't ignored by its project.
+    fn is_workspace_file(&self, path: &Path) -> bool {
+        let folders = self.workspace_folders.read().unwrap();
+
+        is_source_file(path)
+            && folders.iter().any(|folder| path.starts_with(folder))
+            && !self.settings.read().unwrap().is_ignored(path)
+    }
+
+    /// Reads and analyzes a file from the disk without blocking the handler,
+    /// `None` when it can't be read.
+    async fn load_file(&self, path: &Path) -> Option<Snapshot> {
+        let uri = Url::from_file_path(path).ok()?;
+        let encoding = *self.encoding.read().unwrap();
+        let settings = self.get_settings(&uri);
+        let snapshot_map = self.snapshot_map.clone();
+        let path = path.to_owned();
+
+        task::spawn_blocking(move || {
+            load_file(&path, encoding, &settings, &|module| {
+                get_exports(&snapshot_map, module)
+            })
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+
+    /// Returns the paths of the files importing the module which aren't
+    /// opened in the editor.
+    fn get_closed_dependents(&self, uri: &Url) -> Vec<PathBuf> {
+        self.snapshot_map
+            .iter()
+            .filter(|entry| !self.open_documents.contains(entry.key()))
+            .filter(|entry| {
+                entry
+                    .imports
+                    .iter()
+                    .any(|import| import.uri.as_ref() == Some(uri))
+            })
+            .filter_map(|entry| Url::parse(entry.key()).ok()?.to_file_path().ok())
+            .collect()
+    }
+
+    /// Stores the analysis of a file read from the disk, the files opened in
+    /// the editor are left untouched.
+    fn index_file(&self, path: &Path, snapshot: Snapshot) {
+        let Ok(uri) = Url::from_file_path(path) else {
+            return;
+        };
+        let key = uri.to_string();
+
+        if self.open_documents.contains(&key) {
+            return;
+        }
+
+        self.document_map
+            .insert(key.clone(), snapshot.document.clone());
+        self.snapshot_map.insert(key, Arc::new(snapshot));
+    }
+
+    async fn register_capabilities(&self) {
+        let watch_files = self.watch_files.load(Ordering::Relaxed);
+        let watch_configuration = self.watch_configuration.load(Ordering::Relaxed);
+        let mut registrations = Vec::new();
+
+        if watch_files {
+            let options = DidChangeWatchedFilesRegistrationOptions {
+                watchers: vec![
+                    FileSystemWatcher {
+                        glob_pattern: GlobPattern::String(format!("**/*.{}", EXTENSION)),
+                        kind: None,
+                    },
+                    FileSystemWatcher {
+                        glob_pattern: GlobPattern::String(format!("**/{}", CONFIG_FILE)),
+                        kind: None,
+                    },
+                ],
+            };
+
+            registrations.push(Registration {
+                id: "icelang-files".to_owned(),
+                method: "workspace/didChangeWatchedFiles".to_owned(),
+                register_options: serde_json::to_value(options).ok(),
+            });
+        }
+
+        if watch_configuration {
+            registrations.push(Registration {
+                id: "icelang-configuration".to_owned(),
+                method: "workspace/didChangeConfiguration".to_owned(),
+                register_options: Some(json!({ "section": SECTION })),
+            });
+        }
+
+        if registrations.is_empty() {
+            return;
+        }
+
+        if let Err(err) = self.client.register_capability(registrations).await {
+            let message = format!("Failed to register the capabilities: {}", err);
+            self.client.log_message(MessageType::ERROR, message).await;
+        }
+    }
+
+    fn get_settings(&self, uri: &Url) -> Settings {
+        self.settings.read().unwrap().get(uri)
+    }
+
+    /// Reads the global settings and the settings of each workspace folder,
+    /// invalid settings are reported and the previous ones are kept.
+    async fn load_settings(&self) {
+        let folders = self.workspace_folders.read().unwrap().clone();
+        let root = folders.first().cloned();
+        let scopes: Vec<_> = iter::once(None)
+            .chain(folders.into_iter().map(Some))
+            .collect();
+        let items = scopes
+            .iter()
+            .map(|folder| ConfigurationItem {
+                scope_uri: folder
+                    .as_ref()
+                    .and_then(|folder| Url::from_directory_path(folder).ok()),
+                section: Some(SECTION.to_owned()),
+            })
+            .collect();
+        let values = match self.client.configuration(items).await {
+            Ok(values) => values,
+            Err(err) => {
+                let message = format!("Failed to read the settings: {}", err);
+                self.client.log_message(MessageType::ERROR, message).await;
+                return;
+            }
+        };
+        let previous = self.settings.read().unwrap().clone();
+        let mut global = previous.global.clone();
+        let mut folders = Vec::new();
+
+        for (folder, value) in scopes.into_iter().zip(values) {
+            let scope = match &folder {
+                Some(folder) => format!(" of '{}'", folder.display()),
+                None => String::new(),
+            };
+            let parsed = match Settings::parse(value, folder.as_deref().or(root.as_deref())) {
+                Ok(parsed) => parsed,
+                Err(err) => {
+                    let message = format!("Invalid icelang settings{}: {}", scope, err);
+                    self.client.show_message(MessageType::ERROR, message).await;
+
+                    match &folder {
+                        Some(folder) => previous.get_editor_settings(folder).clone(),
+                        None => previous.global.clone(),
+                    }
+                }
+            };
+
+            for problem in parsed.validate() {
+                let message = format!("Invalid icelang settings{}: {}", scope, problem);
+                self.client
+                    .show_message(MessageType::WARNING, message)
+                    .await;
+            }
+
+            match folder {
+                Some(folder) => folders.push((folder, parsed)),
+                None => global = parsed,
+            }
+        }
+
+        let mut settings = self.settings.write().unwrap();
+
+        settings.global = global;
+        settings.folders = folders;
+    }
+
+    /// Analyzes every file again after the settings changed.
+    async fn reanalyze_all(&self) {
+        let open_documents: Vec<_> = self.open_documents.iter().map(|key| key.clone()).collect();
+
+        for key in open_documents {
+            let version = self.document_map.get(&key).map(|document| document.version);
+
+            if let (Ok(uri), Some(version)) = (Url::parse(&key), version) {
+                self.schedule_analysis(uri, version, Duration::ZERO);
+            }
+        }
+
+        self.index_workspace().await;
+
+        let refresh_inlay_hints = self.refresh_inlay_hints.load(Ordering::Relaxed);
+
+        if refresh_inlay_hints {
+            let _ = self.client.inlay_hint_refresh().await;
         }
     }
+
+    /// The project configuration and the settings take precedence over the
+    /// editor options.
+    fn get_format_options(&self, uri: &Url, options: &FormattingOptions) -> FormatOptions {
+        self.get_settings(uri)
+            .format
+            .unwrap_or_else(|| FormatOptions::from(options))
+    }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let encoding = PositionEncoding::negotiate(&params.capabilities);
+        let work_done_progress = params
+            .capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+
+        self.progress.set_supported(work_done_progress);
+
+        *self.encoding.write().unwrap() = encoding;
+
+        let folders = match &params.workspace_folders {
+            Some(folders) => folders.iter().map(|folder| &folder.uri).collect(),
+            None => params.root_uri.iter().collect::<Vec<_>>(),
+        };
+        let watch_files = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files)
+            .and_then(|capability| capability.dynamic_registration)
+            .unwrap_or(false);
+
+        *self.workspace_folders.write().unwrap() = folders
+            .into_iter()
+            .filter_map(|uri| uri.to_file_path().ok())
+            .collect();
+        self.watch_files.store(watch_files, Ordering::Relaxed);
+
+        // the initialization options are used when the settings can't be pulled
+        let options = params.initialization_options.unwrap_or_default();
+        let root = self.workspace_folders.read().unwrap().first().cloned();
+
+        match Settings::parse(options, root.as_deref()) {
+            Ok(settings) => self.settings.write().unwrap().global = settings,
+            Err(err) => {
+                let message = format!("Invalid initialization options: {}", err);
+                self.client.show_message(MessageType::ERROR, message).await;
+            }
+        }
+
+        let workspace = params.capabilities.workspace.as_ref();
+        let configuration = workspace
+            .and_then(|workspace| workspace.configuration)
+            .unwrap_or(false);
+        let watch_configuration = workspace
+            .and_then(|workspace| workspace.did_change_configuration)
+            .and_then(|capability| capability.dynamic_registration)
+            .unwrap_or(false);
+        let refresh_inlay_hints = workspace
+            .and_then(|workspace| workspace.inlay_hint.as_ref())
+            .and_then(|inlay_hint| inlay_hint.refresh_support)
+            .unwrap_or(false);
+
+        self.configuration.store(configuration, Ordering::Relaxed);
+        self.watch_configuration
+            .store(configuration && watch_configuration, Ordering::Relaxed);
+        self.refresh_inlay_hints
+            .store(refresh_inlay_hints, Ordering::Relaxed);
+
+        let pull_diagnostics = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .is_some_and(|text_document| text_document.diagnostic.is_some());
+        let refresh_diagnostics = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.diagnostic.as_ref())
+            .and_then(|diagnostic| diagnostic.refresh_support)
+            .unwrap_or(false);
+
+        self.pull_diagnostics
+            .store(pull_diagnostics, Ordering::Relaxed);
+        self.refresh_diagnostics
+            .store(pull_diagnostics && refresh_diagnostics, Ordering::Relaxed);
+
         Ok(InitializeResult {
             offset_encoding: None,
             capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                position_encoding: Some(encoding.kind()),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                        ..Default::default()
+                    },
                 )),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
                 completion_provider: Some(CompletionOptions::default()),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::REFACTOR_EXTRACT,
+                            CodeActionKind::REFACTOR_INLINE,
+                            CodeActionKind::REFACTOR_REWRITE,
+                            REMOVE_UNREACHABLE.clone(),
+                        ]),
+                        ..Default::default()
+                    },
+                )),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+                    first_trigger_character: "}".to_owned(),
+                    more_trigger_character: Some(vec![
+                        ";".to_owned(),
+                        "\n".to_owned(),
+                        ",".to_owned(),
+                    ]),
+                }),
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
+                    DiagnosticOptions {
+                        identifier: Some("icelang".to_owned()),
+                        inter_file_dependencies: true,
+                        workspace_diagnostics: true,
+                        ..Default::default()
+                    },
+                )),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![CHECK_WORKSPACE.to_owned()],
+                    ..Default::default()
+                }),
                 ..Default::default()
             },
             ..Default::default()
@@ -40,6 +804,16 @@ impl LanguageServer for Backend {
         self.client
             .log_message(MessageType::INFO, "server initialized!")
             .await;
+
+        let configuration = self.configuration.load(Ordering::Relaxed);
+
+        self.register_capabilities().await;
+
+        if configuration {
+            self.load_settings().await;
+        }
+
+        self.index_workspace().await;
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -50,21 +824,21 @@ impl LanguageServer for Backend {
         let uri = params.text_document.uri.clone();
         let version = params.text_document.version;
 
-        if let Some(mut document) = Document::from_params(params) {
+        let encoding = *self.encoding.read().unwrap();
+
+        if let Some(document) = Document::from_params(params, encoding) {
             self.client
                 .log_message(MessageType::INFO, "file opened!")
                 .await;
 
-            let content = &document.content.as_bytes();
-            let tree = &document.tree;
-            let result = analyze(content, tree);
-
-            document.declarations = result.declarations;
+            if let Ok(path) = uri.to_file_path() {
+                self.discover_projects(&[path]).await;
+            }
 
-            self.document_map.insert(uri.to_string(), document);
-            self.client
-                .publish_diagnostics(uri, result.diagnostics, Some(version))
-                .await;
+            self.open_documents.insert(uri.to_string());
+            self.document_map
+                .insert(uri.to_string(), Arc::new(document));
+            self.schedule_analysis(uri, version, Duration::ZERO);
         } else {
             self.client
                 .log_message(MessageType::ERROR, "'textDocument/didOpen' failed")
@@ -76,30 +850,234 @@ impl LanguageServer for Backend {
         let uri = params.text_document.uri.clone();
         let version = params.text_document.version;
 
-        if let Some(mut document) = self.document_map.get_mut(&uri.to_string()) {
-            document.did_change(params);
+        match self.document_map.get_mut(&uri.to_string()) {
+            Some(mut document) => *document = Arc::new(document.did_change(params)),
+            None => return,
+        }
 
-            let content = &document.content.as_bytes();
-            let tree = &document.tree;
-            let result = analyze(content, tree);
+        self.schedule_analysis(uri, version, DEBOUNCE);
+    }
 
-            document.declarations = result.declarations;
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        let uri = params.text_document.uri;
+        let watch_files = self.watch_files.load(Ordering::Relaxed);
 
-            self.client
-                .publish_diagnostics(uri, result.diagnostics, Some(version))
-                .await;
+        // without a file watcher, saving is the only hint that the disk changed,
+        // the saved document is open so only the closed files importing it are
+        // read again
+        if !watch_files {
+            for path in self.get_closed_dependents(&uri) {
+                if let Some(snapshot) = self.load_file(&path).await {
+                    self.index_file(&path, snapshot);
+                }
+            }
         }
+
+        self.update_dependents(&uri).await;
     }
 
-    async fn did_save(&self, _: DidSaveTextDocumentParams) {
-        self.client
-            .log_message(MessageType::INFO, "file saved!")
-            .await;
+    /// Drops the editor state of the document, workspace files go back to
+    /// their content on the disk while the other files are forgotten.
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        let key = uri.to_string();
+
+        self.open_documents.remove(&key);
+
+        if let Some((_, analysis)) = self.analyses.remove(&key) {
+            analysis.abort();
+        }
+
+        let path = uri
+            .to_file_path()
+            .ok()
+            .filter(|path| self.is_workspace_file(path));
+        let snapshot = match &path {
+            Some(path) => self.load_file(path).await,
+            None => None,
+        };
+
+        match path.zip(snapshot) {
+            Some((path, snapshot)) => {
+                let diagnostics = get_diagnostics(&snapshot);
+
+                self.index_file(&path, snapshot);
+                self.publish_diagnostics(uri.clone(), diagnostics).await;
+            }
+            None => {
+                self.document_map.remove(&key);
+                self.snapshot_map.remove(&key);
+                self.publish_diagnostics(uri.clone(), vec![]).await;
+            }
+        }
+
+        self.update_dependents(&uri).await;
+        self.refresh_diagnostics().await;
     }
-    async fn did_close(&self, _: DidCloseTextDocumentParams) {
-        self.client
-            .log_message(MessageType::INFO, "file closed!")
-            .await;
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        let configuration = self.configuration.load(Ordering::Relaxed);
+
+        if configuration {
+            self.load_settings().await;
+        } else if let Some(value) = params.settings.get(SECTION) {
+            let root = self.workspace_folders.read().unwrap().first().cloned();
+
+            match Settings::parse(value.clone(), root.as_deref()) {
+                Ok(settings) => self.settings.write().unwrap().global = settings,
+                Err(err) => {
+                    let message = format!("Invalid icelang settings: {}", err);
+                    self.client.show_message(MessageType::ERROR, message).await;
+                    return;
+                }
+            }
+        } else {
+            return;
+        }
+
+        self.reanalyze_all().await;
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let mut projects_changed = false;
+
+        for event in params.changes {
+            let key = event.uri.to_string();
+            let path = event.uri.to_file_path().ok();
+
+            if let Some(path) = path.filter(|path| path.ends_with(CONFIG_FILE)) {
+                self.load_project(&path).await;
+                projects_changed = true;
+                continue;
+            }
+
+            // the editor content takes precedence over the disk
+            if self.open_documents.contains(&key) {
+                continue;
+            }
+
+            let Ok(path) = event.uri.to_file_path() else {
+                continue;
+            };
+
+            if !is_source_file(&path) {
+                continue;
+            }
+
+            if event.typ == FileChangeType::DELETED || !self.is_workspace_file(&path) {
+                self.document_map.remove(&key);
+                self.snapshot_map.remove(&key);
+            } else if let Some(snapshot) = self.load_file(&path).await {
+                self.index_file(&path, snapshot);
+            }
+
+            self.update_dependents(&event.uri).await;
+        }
+
+        match projects_changed {
+            true => self.reanalyze_all().await,
+            false => self.refresh_diagnostics().await,
+        }
+    }
+
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        let items = match self.get_current_snapshot(&params.text_document.uri).await {
+            Some(snapshot) => get_diagnostics(&snapshot),
+            None => vec![],
+        };
+        let result_id = get_result_id(&items);
+        let report = match params.previous_result_id == Some(result_id.clone()) {
+            true => DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                related_documents: None,
+                unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                    result_id,
+                },
+            }),
+            false => DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                related_documents: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: Some(result_id),
+                    items,
+                },
+            }),
+        };
+
+        Ok(DocumentDiagnosticReportResult::Report(report))
+    }
+
+    /// Reports the closed files of the index, the open documents are pulled
+    /// one by one by the client.
+    async fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> Result<WorkspaceDiagnosticReportResult> {
+        let mut previous: HashMap<_, _> = params
+            .previous_result_ids
+            .into_iter()
+            .map(|previous| (previous.uri.to_string(), previous.value))
+            .collect();
+        let mut files: Vec<_> = self
+            .snapshot_map
+            .iter()
+            .filter(|entry| !self.open_documents.contains(entry.key()))
+            .map(|entry| (entry.key().clone(), get_diagnostics(entry.value())))
+            .collect();
+        let projects = self.settings.read().unwrap().projects.clone();
+        let mut items = Vec::new();
+
+        // the errors of the project files
+        files.extend(projects.iter().filter_map(|project| {
+            let uri = Url::from_file_path(&project.path).ok()?;
+
+            Some((uri.to_string(), project.diagnostics.clone()))
+        }));
+
+        for (key, diagnostics) in files {
+            let Ok(uri) = Url::parse(&key) else {
+                continue;
+            };
+            let result_id = get_result_id(&diagnostics);
+
+            if previous.remove(&key) == Some(result_id.clone()) {
+                items.push(WorkspaceDocumentDiagnosticReport::Unchanged(
+                    WorkspaceUnchangedDocumentDiagnosticReport {
+                        uri,
+                        version: None,
+                        unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                            result_id,
+                        },
+                    },
+                ));
+            } else {
+                items.push(full_report(uri, Some(result_id), diagnostics));
+            }
+        }
+
+        // files removed from the index since the last pull
+        for key in previous.into_keys() {
+            if let Ok(uri) = Url::parse(&key) {
+                if !self.open_documents.contains(&key) {
+                    items.push(full_report(uri, None, vec![]));
+                }
+            }
+        }
+
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items },
+        ))
+    }
+
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        match params.command.as_str() {
+            CHECK_WORKSPACE => Ok(self.check_workspace().await),
+            _ => Err(Error::invalid_params(format!(
+                "unknown command '{}'",
+                params.command
+            ))),
+        }
     }
 
     // TODO: handle variables state & field completion
@@ -116,10 +1094,12 @@ impl LanguageServer for Backend {
                 ..Default::default()
             });
         }
-        
+
         // FIXME: use symbol table
-        if let Some(document) = self.document_map.get(&uri.to_string()) {
-            for decl in document.declarations.get_declared_at(position) {
+        if let Some(snapshot) = self.get_current_snapshot(&uri).await {
+            let position = snapshot.document.decode_position(position);
+
+            for decl in snapshot.declarations.get_declared_at(position) {
                 let kind = match decl.kind {
                     DeclarationKind::Variable => CompletionItemKind::VARIABLE,
                     DeclarationKind::Function(_) => CompletionItemKind::FUNCTION,
@@ -137,7 +1117,103 @@ impl LanguageServer for Backend {
             }
         }
 
-        Ok(Some(completions).map(CompletionResponse::Array))
+        Ok(Some(CompletionResponse::Array(completions)))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri.clone();
+
+        if let Some(snapshot) = self.get_current_snapshot(&uri).await {
+            let document = &snapshot.document;
+            let mut params = params;
+
+            params.range = document.decode_range(params.range);
+
+            for diagnostic in &mut params.context.diagnostics {
+                diagnostic.range = document.decode_range(diagnostic.range);
+            }
+
+            let actions = get_code_actions(&snapshot, &params)
+                .into_iter()
+                .map(|action| match action {
+                    CodeActionOrCommand::CodeAction(action) => {
+                        CodeActionOrCommand::CodeAction(encode_action(document, action))
+                    }
+                    command => command,
+                })
+                .collect();
+
+            return Ok(Some(actions));
+        }
+
+        Ok(None)
+    }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document.uri;
+
+        if let Some(document) = self.get_document(&uri) {
+            let options = self.get_format_options(&uri, &params.options);
+            let edits = format_document(&document, &options);
+
+            return Ok(edits.map(|edits| document.encode_edits(edits)));
+        }
+
+        Ok(None)
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document.uri;
+
+        if let Some(document) = self.get_document(&uri) {
+            let options = self.get_format_options(&uri, &params.options);
+            let range = document.decode_range(params.range);
+            let edits = format_range(&document, range, &options);
+
+            return Ok(edits.map(|edits| document.encode_edits(edits)));
+        }
+
+        Ok(None)
+    }
+
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+
+        if let Some(document) = self.get_document(&uri) {
+            let options = self.get_format_options(&uri, &params.options);
+            let position = document.decode_position(position);
+            let edits = format_on_type(&document, position, &params.ch, &options);
+
+            return Ok(edits.map(|edits| document.encode_edits(edits)));
+        }
+
+        Ok(None)
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let uri = params.text_document.uri;
+        let Some(snapshot) = self.get_current_snapshot(&uri).await else {
+            return Ok(None);
+        };
+        let document = &snapshot.document;
+        let settings = self.get_settings(&uri);
+        let range = document.decode_range(params.range);
+        let hints = get_inlay_hints(&snapshot, range, &settings.inlay_hints)
+            .into_iter()
+            .map(|hint| InlayHint {
+                position: document.encode_position(hint.position),
+                ..hint
+            })
+            .collect();
+
+        Ok(Some(hints))
     }
 
     async fn goto_definition(&self, _: GotoDefinitionParams) -> Result<Option<GotoDefinitionResponse>> {
@@ -156,3 +1232,131 @@ impl LanguageServer for Backend {
         todo!()
     }
 }
+
+/// Looks up the exports of a module in the index, modules which aren't
+/// indexed are read from the disk.
+fn get_exports(snapshot_map: &DashMap<String, Arc<Snapshot>>, uri: &Url) -> Option<Exports> {
+    match snapshot_map.get(uri.as_str()) {
+        Some(snapshot) => Some(snapshot.exports.clone()),
+        None => load_exports(uri),
+    }
+}
+
+/// Re-analyzes the indexed files which import the module, returns the new
+/// snapshots of the files which didn't change in the meantime.
+fn analyze_dependents(
+    document_map: &DashMap<String, Arc<Document>>,
+    snapshot_map: &DashMap<String, Arc<Snapshot>>,
+    uri: &Url,
+    settings: &SettingsMap,
+) -> Vec<(Url, Arc<Snapshot>)> {
+    let dependents: Vec<_> = snapshot_map
+        .iter()
+        .filter(|entry| entry.key() != uri.as_str())
+        .filter(|entry| {
+            entry
+                .imports
+                .iter()
+                .any(|import| import.uri.as_ref() == Some(uri))
+        })
+        .filter_map(|entry| Url::parse(entry.key()).ok())
+        .collect();
+    let mut snapshots = Vec::new();
+
+    for dependent in dependents {
+        let key = dependent.to_string();
+        let Some(document) = document_map.get(&key).map(|document| document.clone()) else {
+            continue;
+        };
+        let settings = settings.get(&dependent);
+        let snapshot = Snapshot::analyze(document.clone(), &dependent, &settings, &|module| {
+            get_exports(snapshot_map, module)
+        });
+        let snapshot = Arc::new(snapshot);
+        let is_current = document_map
+            .get(&key)
+            .is_some_and(|current| Arc::ptr_eq(&current, &document));
+
+        if is_current {
+            snapshot_map.insert(key, snapshot.clone());
+            snapshots.push((dependent, snapshot));
+        }
+    }
+
+    snapshots
+}
+
+async fn publish_dependents(
+    client: &Client,
+    open_documents: &DashSet<String>,
+    dependents: Vec<(Url, Arc<Snapshot>)>,
+    pull_diagnostics: bool,
+    refresh_diagnostics: bool,
+) {
+    if dependents.is_empty() {
+        return;
+    }
+
+    if pull_diagnostics {
+        if refresh_diagnostics {
+            let _ = client.workspace_diagnostic_refresh().await;
+        }
+
+        return;
+    }
+
+    for (uri, snapshot) in dependents {
+        // the files loaded from the disk have no version
+        let version = open_documents
+            .contains(uri.as_str())
+            .then_some(snapshot.document.version);
+
+        client
+            .publish_diagnostics(uri, get_diagnostics(&snapshot), version)
+            .await;
+    }
+}
+
+fn get_diagnostics(snapshot: &Snapshot) -> Vec<Diagnostic> {
+    snapshot
+        .document
+        .encode_diagnostics(snapshot.diagnostics.clone())
+}
+
+/// Identifies a diagnostics report by its content, so that a file whose
+/// diagnostics didn't change is reported as unchanged.
+fn get_result_id(diagnostics: &[Diagnostic]) -> String {
+    let mut hasher = DefaultHasher::new();
+
+    serde_json::to_string(diagnostics)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+
+    format!("{:x}", hasher.finish())
+}
+
+fn full_report(
+    uri: Url,
+    result_id: Option<String>,
+    items: Vec<Diagnostic>,
+) -> WorkspaceDocumentDiagnosticReport {
+    WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+        uri,
+        version: None,
+        full_document_diagnostic_report: FullDocumentDiagnosticReport { result_id, items },
+    })
+}
+
+fn encode_action(document: &Document, mut action: CodeAction) -> CodeAction {
+    if let Some(changes) = action.edit.as_mut().and_then(|edit| edit.changes.as_mut()) {
+        for edits in changes.values_mut() {
+            *edits = document.encode_edits(std::mem::take(edits));
+        }
+    }
+
+    if let Some(diagnostics) = action.diagnostics.take() {
+        action.diagnostics = Some(document.encode_diagnostics(diagnostics));
+    }
+
+    action
+}
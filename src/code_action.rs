@@ -0,0 +1,647 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionDisabled, CodeActionKind, CodeActionOrCommand, CodeActionParams,
+    Diagnostic, Position, Range, TextEdit, Url, WorkspaceEdit,
+};
+
+use tree_sitter::Node;
+
+mod convert;
+mod extract;
+mod inline;
+mod simplify;
+mod unreachable;
+
+use crate::{
+    analyzer::{skip_identifer, Identifier},
+    ast::{has_side_effects, NodeType},
+    builtins::KEYWORDS,
+    declarations::Declaration,
+    diagnostic::DiagnosticData,
+    document::{Document, Snapshot},
+    utils::{get_node_range, point_to_position, position_to_point},
+};
+
+const INDENT: &str = "    ";
+
+lazy_static! {
+    pub static ref REMOVE_UNREACHABLE: CodeActionKind =
+        CodeActionKind::new("source.removeUnreachable");
+}
+
+pub fn get_code_actions(
+    snapshot: &Snapshot,
+    params: &CodeActionParams,
+) -> Vec<CodeActionOrCommand> {
+    CodeActionProvider::new(snapshot, params).provide(params)
+}
+
+struct CodeActionProvider<'a> {
+    document: &'a Document,
+    snapshot: &'a Snapshot,
+    uri: &'a Url,
+    only: Option<&'a Vec<CodeActionKind>>,
+    actions: Vec<CodeActionOrCommand>,
+}
+
+impl<'a> CodeActionProvider<'a> {
+    fn new(snapshot: &'a Snapshot, params: &'a CodeActionParams) -> Self {
+        Self {
+            document: &snapshot.document,
+            snapshot,
+            uri: &params.text_document.uri,
+            only: params.context.only.as_ref(),
+            actions: Vec::new(),
+        }
+    }
+
+    fn provide(mut self, params: &CodeActionParams) -> Vec<CodeActionOrCommand> {
+        if self.wants(&CodeActionKind::QUICKFIX) {
+            for diagnostic in &params.context.diagnostics {
+                if let Some(data) = DiagnosticData::from_diagnostic(diagnostic) {
+                    self.quick_fix(diagnostic, data);
+                }
+            }
+        }
+
+        if self.wants(&CodeActionKind::REFACTOR_EXTRACT) {
+            self.extract_variable(params.range);
+            self.extract_function(params.range);
+        }
+
+        if self.wants(&CodeActionKind::REFACTOR_INLINE) {
+            self.inline(params.range);
+        }
+
+        if self.wants(&CodeActionKind::REFACTOR_REWRITE) {
+            self.convert_function(params.range);
+            self.convert_condition(params.range);
+            self.simplify(params.range);
+        }
+
+        if self.wants(&REMOVE_UNREACHABLE) {
+            self.remove_all_unreachable();
+        }
+
+        self.actions
+    }
+
+    fn wants(&self, kind: &CodeActionKind) -> bool {
+        match self.only {
+            Some(only) => only.iter().any(|value| {
+                let value = value.as_str();
+                let kind = kind.as_str();
+
+                kind == value || kind.starts_with(&format!("{}.", value))
+            }),
+            None => true,
+        }
+    }
+
+    fn quick_fix(&mut self, diagnostic: &Diagnostic, data: DiagnosticData) {
+        match data {
+            DiagnosticData::Undeclared { name, suggestions } => {
+                let node = self.get_node_at(diagnostic.range);
+
+                if let Some(node) = node.filter(|node| self.get_text(node) == name) {
+                    self.replace_undeclared(diagnostic, &name, suggestions);
+                    self.create_undeclared(diagnostic, &node, &name);
+                }
+            }
+            DiagnosticData::Unreachable => self.remove_unreachable(diagnostic),
+            DiagnosticData::Simplify { lint, replacement } => {
+                self.fix_simplification(diagnostic, lint, replacement)
+            }
+            DiagnosticData::Assign => {
+                let node = self.get_node_at(diagnostic.range);
+                // the value of a lambda body or a match arm can't become a statement
+                let statement = node
+                    .and_then(|node| find_parent(&node, NodeType::StmtExpression))
+                    .filter(|statement| {
+                        statement
+                            .parent()
+                            .is_some_and(|parent| matches!(parent.kind(), "stmt_block" | "program"))
+                    });
+
+                if let Some(statement) = statement {
+                    self.assign_result(diagnostic, &statement);
+                }
+            }
+        }
+    }
+
+    fn replace_undeclared(
+        &mut self,
+        diagnostic: &Diagnostic,
+        name: &str,
+        suggestions: Vec<String>,
+    ) {
+        for (index, suggestion) in suggestions.into_iter().enumerate() {
+            let title = format!("Replace '{}' with '{}'", name, suggestion);
+            let edit = TextEdit::new(diagnostic.range, suggestion);
+            let mut action = self.new_action(title, CodeActionKind::QUICKFIX, vec![edit]);
+
+            action.diagnostics = Some(vec![diagnostic.clone()]);
+            action.is_preferred = Some(index == 0);
+
+            self.actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+    }
+
+    fn create_undeclared(&mut self, diagnostic: &Diagnostic, node: &Node, name: &str) {
+        let callee = node
+            .parent()
+            .filter(|parent| NodeType::from(parent) == NodeType::ExprCall)
+            .filter(|parent| parent.child_by_field_name("func") == Some(*node));
+
+        let (title, edit) = if let Some(call) = callee {
+            let Some(statement) = get_top_level_statement(node) else {
+                return;
+            };
+            let args = call.child_by_field_name("args").unwrap();
+            let params = self.get_param_names(&args);
+            let position = point_to_position(statement.start_position());
+            let text = format!("function {}({}) {{}}\n\n", name, params.join(", "));
+            let title = format!("Create function '{}'", name);
+
+            (title, TextEdit::new(Range::new(position, position), text))
+        } else {
+            let Some(statement) = get_statement(node) else {
+                return;
+            };
+            let position = point_to_position(statement.start_position());
+            let indent = self.get_indent(&statement);
+            let text = format!("set {} = null\n{}", name, indent);
+            let title = format!("Create variable '{}'", name);
+
+            (title, TextEdit::new(Range::new(position, position), text))
+        };
+
+        let mut action = self.new_action(title, CodeActionKind::QUICKFIX, vec![edit]);
+
+        action.diagnostics = Some(vec![diagnostic.clone()]);
+
+        self.actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+
+    fn assign_result(&mut self, diagnostic: &Diagnostic, statement: &Node) {
+        let Some(expression) = statement.named_child(0) else {
+            return;
+        };
+        let start = point_to_position(statement.start_position());
+        let end = point_to_position(statement.end_position());
+        let scope = statement.parent().unwrap_or(*statement);
+        let name = self.get_unique_name(&self.guess_name(&expression), end, &scope);
+        let title = format!("Assign result to '{}'", name);
+        let text = format!("set {} = ", name);
+        let edit = TextEdit::new(Range::new(start, start), text);
+        let mut action = self.new_action(title, CodeActionKind::QUICKFIX, vec![edit]);
+
+        action.diagnostics = Some(vec![diagnostic.clone()]);
+        action.is_preferred = Some(true);
+
+        self.actions.push(CodeActionOrCommand::CodeAction(action));
+
+        if !has_side_effects(&expression) {
+            let title = "Remove unused expression".to_owned();
+            let edit = TextEdit::new(self.get_removal_range(statement), String::new());
+            let mut action = self.new_action(title, CodeActionKind::QUICKFIX, vec![edit]);
+
+            action.diagnostics = Some(vec![diagnostic.clone()]);
+
+            self.actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+    }
+
+    fn guess_name(&self, node: &Node) -> String {
+        let name = match NodeType::from(node) {
+            NodeType::ExprCall => {
+                node.child_by_field_name("func")
+                    .and_then(|func| match NodeType::from(&func) {
+                        NodeType::ExprIdentifier => Some(func),
+                        NodeType::ExprField => func.child_by_field_name("field"),
+                        _ => None,
+                    })
+            }
+            NodeType::ExprField => node.child_by_field_name("field"),
+            NodeType::ExprGroup => {
+                return match node.named_child(0) {
+                    Some(child) => self.guess_name(&child),
+                    None => "result".to_owned(),
+                }
+            }
+            _ => None,
+        };
+
+        name.map(|name| self.get_text(&name))
+            .map(|name| name.strip_prefix("get_").unwrap_or(name))
+            .filter(|name| !name.is_empty())
+            .unwrap_or("result")
+            .to_owned()
+    }
+
+    fn get_unique_name(&self, base: &str, position: Position, scope: &Node) -> String {
+        let declared = self.snapshot.declarations.get_declared_at(position);
+        let used = self.get_identifiers(scope);
+        let is_taken = |name: &str| {
+            KEYWORDS.contains(&name)
+                || declared.iter().any(|decl| decl.name == name)
+                || used.contains(&name)
+        };
+        let mut name = base.to_owned();
+        let mut count = 2;
+
+        while is_taken(&name) {
+            name = format!("{}{}", base, count);
+            count += 1;
+        }
+
+        name
+    }
+
+    fn get_identifiers(&self, node: &Node) -> Vec<&'a str> {
+        let mut identifiers = Vec::new();
+        let mut cursor = node.walk();
+
+        if NodeType::from(node) == NodeType::ExprIdentifier && !is_field_name(node) {
+            identifiers.push(self.get_text(node));
+        }
+
+        for child in node.named_children(&mut cursor) {
+            identifiers.extend(self.get_identifiers(&child));
+        }
+
+        identifiers
+    }
+
+    fn get_removal_range(&self, node: &Node) -> Range {
+        let content = &self.document.content;
+        let before = &content[..node.start_byte()];
+        let after = &content[node.end_byte()..];
+        let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+        let line_end = after.find('\n').map(|index| index + 1);
+        let alone = before[line_start..].trim().is_empty()
+            && after[..line_end.unwrap_or(after.len())].trim().is_empty();
+
+        match (alone, line_end) {
+            (true, Some(_)) => {
+                let start = Position::new(node.start_position().row as u32, 0);
+                let end = Position::new(node.end_position().row as u32 + 1, 0);
+
+                Range::new(start, end)
+            }
+            _ => get_node_range(node),
+        }
+    }
+
+    fn get_param_names(&self, args: &Node) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        let mut cursor = args.walk();
+
+        for (index, arg) in args.named_children(&mut cursor).enumerate() {
+            let name = match NodeType::from(&arg) {
+                NodeType::ExprIdentifier => Some(arg),
+                NodeType::ExprField => arg.child_by_field_name("field"),
+                _ => None,
+            }
+            .map(|node| self.get_text(&node).to_owned())
+            .filter(|name| !name.is_empty() && !names.contains(name))
+            .unwrap_or_else(|| format!("arg{}", index + 1));
+
+            names.push(name);
+        }
+
+        names
+    }
+
+    fn get_indent(&self, node: &Node) -> &'a str {
+        let content = &self.document.content;
+        let line_start = content[..node.start_byte()]
+            .rfind('\n')
+            .map(|index| index + 1)
+            .unwrap_or(0);
+        let line = &content[line_start..node.start_byte()];
+        let length = line.len() - line.trim_start().len();
+
+        &line[..length]
+    }
+
+    fn push_disabled(&mut self, title: String, kind: CodeActionKind, reason: &str) {
+        let mut action = self.new_action(title, kind, vec![]);
+
+        action.edit = None;
+        action.disabled = Some(CodeActionDisabled {
+            reason: reason.to_owned(),
+        });
+
+        self.actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+
+    fn get_reference(&self, node: &Node) -> Option<Declaration> {
+        if NodeType::from(node) != NodeType::ExprIdentifier || skip_identifer(node) {
+            return None;
+        }
+
+        self.resolve(node)
+    }
+
+    fn resolve(&self, node: &Node) -> Option<Declaration> {
+        let identifier = Identifier {
+            name: self.get_text(node).to_owned(),
+            range: get_node_range(node),
+        };
+
+        self.snapshot
+            .declarations
+            .get(&identifier)
+            .filter(|decl| !decl.is_builtin())
+            .cloned()
+    }
+
+    fn new_action(&self, title: String, kind: CodeActionKind, edits: Vec<TextEdit>) -> CodeAction {
+        let changes = HashMap::from([(self.uri.clone(), edits)]);
+
+        CodeAction {
+            title,
+            kind: Some(kind),
+            edit: Some(WorkspaceEdit::new(changes)),
+            ..Default::default()
+        }
+    }
+
+    fn get_node_at(&self, range: Range) -> Option<Node<'a>> {
+        let start = position_to_point(range.start);
+        let end = position_to_point(range.end);
+
+        self.document
+            .tree
+            .root_node()
+            .descendant_for_point_range(start, end)
+    }
+
+    fn get_text(&self, node: &Node) -> &'a str {
+        node.utf8_text(self.document.content.as_bytes()).unwrap()
+    }
+}
+
+fn get_statement<'a>(node: &Node<'a>) -> Option<Node<'a>> {
+    let mut current = *node;
+
+    while let Some(parent) = current.parent() {
+        match parent.kind() {
+            "program" | "stmt_block" => return Some(current),
+            _ => current = parent,
+        }
+    }
+
+    None
+}
+
+fn get_top_level_statement<'a>(node: &Node<'a>) -> Option<Node<'a>> {
+    let mut current = *node;
+
+    while let Some(parent) = current.parent() {
+        match parent.kind() {
+            "program" => return Some(current),
+            _ => current = parent,
+        }
+    }
+
+    None
+}
+
+fn find_parent<'a>(node: &Node<'a>, node_type: NodeType) -> Option<Node<'a>> {
+    let mut current = Some(*node);
+
+    while let Some(value) = current {
+        if NodeType::from(&value) == node_type {
+            return Some(value);
+        }
+
+        current = value.parent();
+    }
+
+    None
+}
+
+fn is_field_name(node: &Node) -> bool {
+    match node.parent() {
+        Some(parent) => match NodeType::from(&parent) {
+            NodeType::ExprField => parent.child_by_field_name("field") == Some(*node),
+            NodeType::Prop => parent.child_by_field_name("name") == Some(*node),
+            _ => false,
+        },
+        None => false,
+    }
+}
+
+/// Moves the lines of a text from an indentation level to another one, the
+/// first line is expected to be already stripped of its indentation.
+fn reindent(text: &str, from: &str, to: &str) -> String {
+    text.split('\n')
+        .enumerate()
+        .map(|(index, line)| match index {
+            0 => line.to_owned(),
+            _ if line.trim().is_empty() => String::new(),
+            _ => format!(
+                "{}{}",
+                to,
+                line.strip_prefix(from).unwrap_or(line.trim_start())
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn get_descendants<'tree>(node: &Node<'tree>) -> Vec<Node<'tree>> {
+    let mut result = vec![*node];
+    let mut cursor = node.walk();
+
+    for child in node.named_children(&mut cursor) {
+        result.extend(get_descendants(&child));
+    }
+
+    result
+}
+
+fn is_same(a: &Declaration, b: &Declaration) -> bool {
+    a.name == b.name && a.name_range == b.name_range
+}
+
+fn get_root(node: Node) -> Node {
+    match NodeType::from(&node) {
+        NodeType::ExprField | NodeType::ExprIndex => match node.child_by_field_name("arg") {
+            Some(arg) => get_root(arg),
+            None => node,
+        },
+        _ => node,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cmp::Reverse, sync::Arc};
+
+    use tower_lsp::lsp_types::{CodeActionContext, TextDocumentIdentifier};
+
+    use super::*;
+    use crate::{document::PositionEncoding, settings::Settings};
+
+    #[derive(Debug, PartialEq)]
+    pub(super) enum Outcome {
+        Applied(String),
+        Disabled(String),
+        Missing,
+    }
+
+    /// Runs the action with the given title on the selection between `«` and
+    /// `»`, the diagnostics of the selection are passed to the quick fixes.
+    pub(super) fn run(source: &str, title: &str) -> Outcome {
+        let start = source.find('«').expect("missing selection start");
+        let content = source.replacen('«', "", 1);
+        let end = content.find('»').expect("missing selection end");
+        let content = content.replacen('»', "", 1);
+        let uri = Url::parse("file:///test.ic").unwrap();
+        let document = Document::new(0, content.clone(), PositionEncoding::Utf8).unwrap();
+        let snapshot = Snapshot::analyze(Arc::new(document), &uri, &Settings::default(), &|_| None);
+        let range = Range::new(to_position(&content, start), to_position(&content, end));
+        let diagnostics = snapshot
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.range.start <= range.end)
+            .filter(|diagnostic| range.start <= diagnostic.range.end)
+            .cloned()
+            .collect();
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range,
+            context: CodeActionContext {
+                diagnostics,
+                only: None,
+                trigger_kind: None,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let action =
+            get_code_actions(&snapshot, &params)
+                .into_iter()
+                .find_map(|action| match action {
+                    CodeActionOrCommand::CodeAction(action) if action.title == title => {
+                        Some(action)
+                    }
+                    _ => None,
+                });
+
+        match action {
+            None => Outcome::Missing,
+            Some(CodeAction {
+                disabled: Some(disabled),
+                ..
+            }) => Outcome::Disabled(disabled.reason),
+            Some(action) => {
+                let mut changes = action.edit.unwrap().changes.unwrap();
+
+                Outcome::Applied(apply_edits(&content, changes.remove(&uri).unwrap()))
+            }
+        }
+    }
+
+    /// Applies the edits, the edits inserting at the same position keep their
+    /// order.
+    fn apply_edits(content: &str, mut edits: Vec<TextEdit>) -> String {
+        let mut content = content.to_owned();
+
+        edits.reverse();
+        edits.sort_by_key(|edit| Reverse(edit.range.start));
+
+        for edit in edits {
+            let start = to_offset(&content, edit.range.start);
+            let end = to_offset(&content, edit.range.end);
+
+            content.replace_range(start..end, &edit.new_text);
+        }
+
+        content
+    }
+
+    fn to_position(content: &str, offset: usize) -> Position {
+        let before = &content[..offset];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+
+        Position::new(
+            before.matches('\n').count() as u32,
+            (offset - line_start) as u32,
+        )
+    }
+
+    fn to_offset(content: &str, position: Position) -> usize {
+        let line_start: usize = content
+            .split_inclusive('\n')
+            .take(position.line as usize)
+            .map(str::len)
+            .sum();
+
+        line_start + position.character as usize
+    }
+
+    #[test]
+    fn replace_undeclared() {
+        let source = "set count = 1\nprint(«cont»)\n";
+        let distant = "set count = 1\nprint(«xyz»)\n";
+
+        assert_eq!(
+            run(source, "Replace 'cont' with 'count'"),
+            Outcome::Applied("set count = 1\nprint(count)\n".to_owned())
+        );
+        assert_eq!(run(distant, "Replace 'xyz' with 'count'"), Outcome::Missing);
+    }
+
+    #[test]
+    fn create_undeclared() {
+        let variable = "print(«total»)\n";
+        let function = "set a = 1\nset b = «add»(a, 2)\n";
+
+        assert_eq!(
+            run(variable, "Create variable 'total'"),
+            Outcome::Applied("set total = null\nprint(total)\n".to_owned())
+        );
+        assert_eq!(run(variable, "Create function 'total'"), Outcome::Missing);
+        assert_eq!(
+            run(function, "Create function 'add'"),
+            Outcome::Applied(
+                "set a = 1\nfunction add(a, arg2) {}\n\nset b = add(a, 2)\n".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn assign_result() {
+        let source = "set a = 1\n«a + 1»\n";
+        let call = "set a = 1\n«print(a)»\n";
+
+        assert_eq!(
+            run(source, "Assign result to 'result'"),
+            Outcome::Applied("set a = 1\nset result = a + 1\n".to_owned())
+        );
+        assert_eq!(
+            run(source, "Remove unused expression"),
+            Outcome::Applied("set a = 1\n".to_owned())
+        );
+        assert_eq!(run(call, "Remove unused expression"), Outcome::Missing);
+    }
+
+    #[test]
+    fn assign_result_outside_block() {
+        let lambda = "set f = lambda(x) «x * 2»\n";
+        let arm = "set a = 1\nprint(match a {\n    1: «2 + 3»,\n    _: 0,\n})\n";
+
+        for source in [lambda, arm] {
+            assert_eq!(run(source, "Assign result to 'result'"), Outcome::Missing);
+            assert_eq!(run(source, "Remove unused expression"), Outcome::Missing);
+        }
+    }
+}
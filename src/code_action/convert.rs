@@ -0,0 +1,486 @@
+use tower_lsp::lsp_types::{CodeActionKind, CodeActionOrCommand, Range, TextEdit};
+use tree_sitter::Node;
+
+use crate::{
+    ast::{get_precedence, has_side_effects, NodeType},
+    utils::{get_node_range, point_to_position, position_to_point},
+};
+
+use super::{reindent, CodeActionProvider, INDENT};
+
+const EQUALITY_PRECEDENCE: u8 = 3;
+
+/// A branch of a conditional expression, the patterns are empty for the
+/// default branch.
+struct Branch<'tree> {
+    patterns: Vec<Node<'tree>>,
+    body: Node<'tree>,
+}
+
+impl<'a> CodeActionProvider<'a> {
+    pub(super) fn convert_function(&mut self, range: Range) {
+        let Some(mut node) = self.get_node_at(Range::new(range.start, range.start)) else {
+            return;
+        };
+
+        while !matches!(node.kind(), "stmt_func_decl" | "stmt_var_decl") {
+            if matches!(node.kind(), "stmt_block" | "program") {
+                return;
+            }
+
+            node = match node.parent() {
+                Some(parent) => parent,
+                None => return,
+            };
+        }
+
+        match NodeType::from(&node) {
+            NodeType::StmtFuncDecl => self.function_to_lambda(&node, range),
+            NodeType::StmtVarDecl => self.lambda_to_function(&node, range),
+            _ => {}
+        }
+    }
+
+    fn function_to_lambda(&mut self, node: &Node, range: Range) {
+        let name = node.child_by_field_name("name").unwrap();
+        let args = node.child_by_field_name("args").unwrap();
+        let body = node.child_by_field_name("body").unwrap();
+
+        if position_to_point(range.start) > body.start_position() {
+            return;
+        }
+
+        let name_text = self.get_text(&name);
+        let title = "Convert to lambda".to_owned();
+        let Some((decl, _)) = self.get_declaration(&name) else {
+            return;
+        };
+
+        let used_before = self
+            .get_references(&decl)
+            .iter()
+            .any(|reference| reference.start_byte() < node.start_byte());
+
+        if used_before {
+            let reason = format!("'{}' is used before its declaration", name_text);
+            self.push_disabled(title, CodeActionKind::REFACTOR_REWRITE, &reason);
+            return;
+        }
+
+        let header = Range::new(
+            point_to_position(node.start_position()),
+            point_to_position(args.start_position()),
+        );
+        let edit = TextEdit::new(header, format!("set {} = lambda", name_text));
+        let action = self.new_action(title, CodeActionKind::REFACTOR_REWRITE, vec![edit]);
+
+        self.actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+
+    fn lambda_to_function(&mut self, node: &Node, range: Range) {
+        let name = node.child_by_field_name("name").unwrap();
+        let value = node.child_by_field_name("value").unwrap();
+
+        if NodeType::from(&value) != NodeType::ExprLambda {
+            return;
+        }
+
+        let args = value.child_by_field_name("args").unwrap();
+        let body = value.child_by_field_name("body").unwrap();
+
+        if position_to_point(range.start) > body.start_position() {
+            return;
+        }
+
+        let name_text = self.get_text(&name);
+        let title = "Convert to function declaration".to_owned();
+        let Some((decl, _)) = self.get_declaration(&name) else {
+            return;
+        };
+
+        if self.is_reassigned(&decl, node.end_byte(), usize::MAX) {
+            let reason = format!("'{}' is reassigned", name_text);
+            self.push_disabled(title, CodeActionKind::REFACTOR_REWRITE, &reason);
+            return;
+        }
+
+        let header = Range::new(
+            point_to_position(node.start_position()),
+            point_to_position(args.start_position()),
+        );
+        let mut edits = vec![TextEdit::new(header, format!("function {}", name_text))];
+
+        if NodeType::from(&body) != NodeType::StmtBlock {
+            let text = format!("{{ {} }}", self.get_text(&body));
+
+            edits.push(TextEdit::new(get_node_range(&body), text));
+        }
+
+        let action = self.new_action(title, CodeActionKind::REFACTOR_REWRITE, edits);
+
+        self.actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+
+    pub(super) fn convert_condition(&mut self, range: Range) {
+        let Some(mut node) = self.get_node_at(Range::new(range.start, range.start)) else {
+            return;
+        };
+
+        while !matches!(node.kind(), "expr_if" | "expr_match") {
+            if node.kind().starts_with("stmt_") || node.kind() == "program" {
+                return;
+            }
+
+            node = match node.parent() {
+                Some(parent) => parent,
+                None => return,
+            };
+        }
+
+        match NodeType::from(&node) {
+            NodeType::ExprIf => {
+                while let Some(parent) = node.parent().filter(|p| p.kind() == "expr_if") {
+                    node = parent;
+                }
+
+                self.if_to_match(&node);
+            }
+            NodeType::ExprMatch => self.match_to_if(&node),
+            _ => {}
+        }
+    }
+
+    fn if_to_match(&mut self, node: &Node) {
+        let Some((subject, branches)) = self.get_if_branches(node) else {
+            return;
+        };
+
+        let indent = self.get_indent(node);
+        let arm_indent = format!("{}{}", indent, INDENT);
+        let mut arms = Vec::new();
+
+        for branch in branches {
+            let patterns: Vec<_> = branch
+                .patterns
+                .iter()
+                .map(|pattern| self.get_text(pattern))
+                .collect();
+            let pattern = match patterns.is_empty() {
+                true => "_".to_owned(),
+                false => patterns.join(", "),
+            };
+            let body = reindent(self.get_text(&branch.body), indent, &arm_indent);
+
+            arms.push(format!("{}{}: {},", arm_indent, pattern, body));
+        }
+
+        let text = format!(
+            "match {} {{\n{}\n{}}}",
+            self.get_text(&subject),
+            arms.join("\n"),
+            indent
+        );
+        let edit = TextEdit::new(get_node_range(node), text);
+        let title = "Convert to match expression".to_owned();
+        let action = self.new_action(title, CodeActionKind::REFACTOR_REWRITE, vec![edit]);
+
+        self.actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+
+    fn get_if_branches(&self, node: &Node<'a>) -> Option<(Node<'a>, Vec<Branch<'a>>)> {
+        let mut subject: Option<Node<'a>> = None;
+        let mut branches = Vec::new();
+        let mut current = Some(*node);
+
+        while let Some(value) = current {
+            let condition = value.child_by_field_name("condition")?;
+            let body = value.child_by_field_name("body")?;
+            let mut patterns = Vec::new();
+
+            for (lhs, rhs) in self.get_comparisons(&condition)? {
+                let (value_subject, pattern) = match (is_literal(&lhs), is_literal(&rhs)) {
+                    (false, true) => (lhs, rhs),
+                    (true, false) => (rhs, lhs),
+                    _ => return None,
+                };
+
+                match subject {
+                    Some(subject) if self.get_text(&subject) != self.get_text(&value_subject) => {
+                        return None
+                    }
+                    _ => subject = Some(value_subject),
+                }
+
+                patterns.push(pattern);
+            }
+
+            branches.push(Branch { patterns, body });
+            current = None;
+
+            let mut cursor = value.walk();
+            let else_branch = value
+                .children_by_field_name("else_branch", &mut cursor)
+                .find(|child| child.is_named());
+
+            match else_branch {
+                Some(branch) if branch.kind() == "expr_if" => current = Some(branch),
+                Some(branch) => branches.push(Branch {
+                    patterns: vec![],
+                    body: branch,
+                }),
+                None => {}
+            }
+        }
+
+        let subject = subject.filter(|subject| !has_side_effects(subject))?;
+
+        Some((subject, branches)).filter(|(_, branches)| branches.len() > 1)
+    }
+
+    /// Splits a condition made of equalities joined by `or` into pairs of
+    /// compared expressions.
+    fn get_comparisons(&self, node: &Node<'a>) -> Option<Vec<(Node<'a>, Node<'a>)>> {
+        match NodeType::from(node) {
+            NodeType::ExprGroup => self.get_comparisons(&node.named_child(0)?),
+            NodeType::ExprBinary => {
+                let operator = node.child_by_field_name("operator")?;
+                let lhs = node.child_by_field_name("lhs")?;
+                let rhs = node.child_by_field_name("rhs")?;
+
+                match operator.kind() {
+                    "==" => Some(vec![(lhs, rhs)]),
+                    "or" => {
+                        let mut comparisons = self.get_comparisons(&lhs)?;
+                        comparisons.extend(self.get_comparisons(&rhs)?);
+
+                        Some(comparisons)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn match_to_if(&mut self, node: &Node) {
+        let Some(branches) = self.get_match_branches(node) else {
+            return;
+        };
+
+        let title = "Convert to if expression".to_owned();
+        let subject = node.child_by_field_name("value").unwrap();
+
+        if has_side_effects(&subject) {
+            let reason = "The matched value has side effects";
+            self.push_disabled(title, CodeActionKind::REFACTOR_REWRITE, reason);
+            return;
+        }
+
+        let indent = self.get_indent(node);
+        let subject_text = wrap_comparison(&subject, self.get_text(&subject));
+        let mut text = String::new();
+
+        for (index, branch) in branches.iter().enumerate() {
+            let conditions: Vec<_> = branch
+                .patterns
+                .iter()
+                .map(|pattern| {
+                    let pattern = wrap_comparison(pattern, self.get_text(pattern));
+                    format!("{} == {}", subject_text, pattern)
+                })
+                .collect();
+            let from = self.get_indent(&branch.body.parent().unwrap());
+            let body = match NodeType::from(&branch.body) {
+                NodeType::StmtBlock => reindent(self.get_text(&branch.body), from, indent),
+                _ => format!("{{ {} }}", self.get_text(&branch.body)),
+            };
+
+            if index > 0 {
+                text.push_str(" else ");
+            }
+
+            if !conditions.is_empty() {
+                text.push_str(&format!("if {} ", conditions.join(" or ")));
+            }
+
+            text.push_str(&body);
+        }
+
+        let edit = TextEdit::new(get_node_range(node), text);
+        let action = self.new_action(title, CodeActionKind::REFACTOR_REWRITE, vec![edit]);
+
+        self.actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+
+    fn get_match_branches(&self, node: &Node<'a>) -> Option<Vec<Branch<'a>>> {
+        let body = node.child_by_field_name("body")?;
+        let mut cursor = body.walk();
+        let arms: Vec<_> = body
+            .named_children(&mut cursor)
+            .filter(|arm| arm.kind() != "comment")
+            .collect();
+        let mut branches = Vec::new();
+
+        for (index, arm) in arms.iter().enumerate() {
+            let value = arm.child_by_field_name("value")?;
+
+            match arm.kind() {
+                "match_arm" => {
+                    let pattern = arm.child_by_field_name("pattern")?;
+                    let patterns = get_patterns(&pattern)?;
+
+                    if patterns.iter().any(has_side_effects) {
+                        return None;
+                    }
+
+                    branches.push(Branch {
+                        patterns,
+                        body: value,
+                    });
+                }
+                "default_arm" if index == arms.len() - 1 => branches.push(Branch {
+                    patterns: vec![],
+                    body: value,
+                }),
+                _ => return None,
+            }
+        }
+
+        match branches.first() {
+            Some(branch) if !branch.patterns.is_empty() => Some(branches),
+            _ => None,
+        }
+    }
+}
+
+fn get_patterns<'tree>(node: &Node<'tree>) -> Option<Vec<Node<'tree>>> {
+    match node.kind() {
+        "literal_pattern" => Some(vec![node.named_child(0)?]),
+        "or_pattern" => {
+            let mut patterns = Vec::new();
+            let mut cursor = node.walk();
+
+            for child in node.named_children(&mut cursor) {
+                patterns.extend(get_patterns(&child)?);
+            }
+
+            Some(patterns)
+        }
+        _ => None,
+    }
+}
+
+fn is_literal(node: &Node) -> bool {
+    match NodeType::from(node) {
+        NodeType::ExprLiteral => node
+            .named_child(0)
+            .is_some_and(|child| child.kind() != "self"),
+        NodeType::ExprUnary => {
+            node.child(0).is_some_and(|operator| operator.kind() == "-")
+                && node
+                    .named_child(0)
+                    .is_some_and(|operand| is_literal(&operand))
+        }
+        _ => false,
+    }
+}
+
+fn wrap_comparison(node: &Node, text: &str) -> String {
+    match get_precedence(node) <= EQUALITY_PRECEDENCE {
+        true => format!("({})", text),
+        false => text.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::code_action::tests::{run, Outcome};
+
+    #[test]
+    fn function_to_lambda() {
+        let source = "«function» add(a, b) {\n    return a + b\n}\n\nprint(add(1, 2))\n";
+        let expected = "set add = lambda(a, b) {\n    return a + b\n}\n\nprint(add(1, 2))\n";
+        let used_before = "print(add(1, 2))\n«function» add(a, b) {\n    return a + b\n}\n";
+
+        assert_eq!(
+            run(source, "Convert to lambda"),
+            Outcome::Applied(expected.to_owned())
+        );
+        assert_eq!(
+            run(used_before, "Convert to lambda"),
+            Outcome::Disabled("'add' is used before its declaration".to_owned())
+        );
+    }
+
+    #[test]
+    fn lambda_to_function() {
+        let source = "set «double» = lambda(x) x * 2\nprint(double(2))\n";
+        let expected = "function double(x) { x * 2 }\nprint(double(2))\n";
+        let reassigned = "set «f» = lambda(x) x\nf = null\n";
+
+        assert_eq!(
+            run(source, "Convert to function declaration"),
+            Outcome::Applied(expected.to_owned())
+        );
+        assert_eq!(
+            run(reassigned, "Convert to function declaration"),
+            Outcome::Disabled("'f' is reassigned".to_owned())
+        );
+        assert_eq!(
+            run("set «a» = 1\n", "Convert to function declaration"),
+            Outcome::Missing
+        );
+    }
+
+    #[test]
+    fn if_to_match() {
+        let source = concat!(
+            "«if» x == 1 {\n",
+            "    print(1)\n",
+            "} else if x == 2 or 3 == x {\n",
+            "    print(2)\n",
+            "} else {\n",
+            "    print(3)\n",
+            "}\n",
+        );
+        let expected = concat!(
+            "match x {\n",
+            "    1: {\n",
+            "        print(1)\n",
+            "    },\n",
+            "    2, 3: {\n",
+            "        print(2)\n",
+            "    },\n",
+            "    _: {\n",
+            "        print(3)\n",
+            "    },\n",
+            "}\n",
+        );
+        let subjects = "«if» x == 1 {} else if y == 2 {}\n";
+
+        assert_eq!(
+            run(source, "Convert to match expression"),
+            Outcome::Applied(expected.to_owned())
+        );
+        assert_eq!(
+            run(subjects, "Convert to match expression"),
+            Outcome::Missing
+        );
+    }
+
+    #[test]
+    fn match_to_if() {
+        let source = "«match» x {\n    1, 2: print(1),\n    _: print(2),\n}\n";
+        let expected = "if x == 1 or x == 2 { print(1) } else { print(2) }\n";
+        let side_effects = "«match» f() {\n    1: 2,\n    _: 3,\n}\n";
+
+        assert_eq!(
+            run(source, "Convert to if expression"),
+            Outcome::Applied(expected.to_owned())
+        );
+        assert_eq!(
+            run(side_effects, "Convert to if expression"),
+            Outcome::Disabled("The matched value has side effects".to_owned())
+        );
+    }
+}
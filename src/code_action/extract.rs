@@ -0,0 +1,723 @@
+use tower_lsp::lsp_types::{CodeActionKind, CodeActionOrCommand, Range, TextEdit};
+use tree_sitter::Node;
+
+use crate::{
+    ast::{has_side_effects, is_assignment, NodeType, FUNCTION_NODE, LOOP_NODE},
+    declarations::Declaration,
+    utils::{get_node_range, point_to_position, position_to_point},
+};
+
+use super::{
+    get_descendants, get_root, get_statement, get_top_level_statement, is_same, reindent,
+    CodeActionProvider, INDENT,
+};
+
+impl<'a> CodeActionProvider<'a> {
+    pub(super) fn extract_variable(&mut self, range: Range) {
+        let Some(expression) = self.get_expression_at(range) else {
+            return;
+        };
+        let Some(statement) = get_statement(&expression) else {
+            return;
+        };
+
+        if !can_extract(&expression, &statement) {
+            return;
+        }
+
+        let scope = statement.parent().unwrap();
+        let position = point_to_position(statement.start_position());
+        let name = self.guess_name(&expression);
+        let name = self.get_unique_name(&name, position, &scope);
+        let title = format!("Extract into variable '{}'", name);
+
+        if is_conditional(&expression, &statement) {
+            let reason = "Expression is only evaluated under a condition";
+            self.push_disabled(title, CodeActionKind::REFACTOR_EXTRACT, reason);
+            return;
+        }
+
+        let declaration = format!(
+            "set {} = {}\n{}",
+            name,
+            self.get_text(&expression),
+            self.get_indent(&statement)
+        );
+        let edits = get_extract_edits(&statement, &declaration, &name, &[expression]);
+        let action = self.new_action(title, CodeActionKind::REFACTOR_EXTRACT, edits);
+
+        self.actions.push(CodeActionOrCommand::CodeAction(action));
+
+        if !has_side_effects(&expression) {
+            let occurrences = self.find_occurrences(&expression, &statement);
+
+            if occurrences.len() > 1 {
+                let title = format!(
+                    "Extract all {} occurrences into variable '{}'",
+                    occurrences.len(),
+                    name
+                );
+                let edits = get_extract_edits(&statement, &declaration, &name, &occurrences);
+                let action = self.new_action(title, CodeActionKind::REFACTOR_EXTRACT, edits);
+
+                self.actions.push(CodeActionOrCommand::CodeAction(action));
+            }
+        }
+    }
+
+    pub(super) fn extract_function(&mut self, range: Range) {
+        if range.start == range.end {
+            return;
+        }
+
+        let Some(statements) = self.get_statements_in(range) else {
+            return;
+        };
+        let first = statements[0];
+        let last = statements[statements.len() - 1];
+        let Some(top_level) = get_top_level_statement(&first) else {
+            return;
+        };
+
+        let root = self.document.tree.root_node();
+        let position = point_to_position(top_level.start_position());
+        let name = self.get_unique_name("extracted", position, &root);
+        let title = format!("Extract into function '{}'", name);
+
+        let returns = match self.get_escaping_flow(&statements) {
+            Ok(returns) => returns,
+            Err(reason) => {
+                self.push_disabled(title, CodeActionKind::REFACTOR_EXTRACT, reason);
+                return;
+            }
+        };
+        let params = self.get_extracted_params(&statements);
+        let results = self.get_extracted_results(&statements);
+
+        let (result, call) = match (results.as_slice(), returns) {
+            ([], false) => (None, format!("{}({})", name, params.join(", "))),
+            ([], true) => (None, format!("return {}({})", name, params.join(", "))),
+            ([(result, declared)], false) => {
+                let call = match declared {
+                    true => format!("set {} = {}({})", result, name, params.join(", ")),
+                    false => format!("{} = {}({})", result, name, params.join(", ")),
+                };
+
+                (Some(result), call)
+            }
+            ([_], true) => {
+                let reason = "Selection both returns and assigns a value used afterwards";
+                self.push_disabled(title, CodeActionKind::REFACTOR_EXTRACT, reason);
+                return;
+            }
+            _ => {
+                let reason = "Selection assigns more than one value used afterwards";
+                self.push_disabled(title, CodeActionKind::REFACTOR_EXTRACT, reason);
+                return;
+            }
+        };
+
+        let base_indent = self.get_indent(&top_level);
+        let body_indent = format!("{}{}", base_indent, INDENT);
+        let text = &self.document.content[first.start_byte()..last.end_byte()];
+        let mut body = format!(
+            "{}{}",
+            body_indent,
+            reindent(text, self.get_indent(&first), &body_indent)
+        );
+
+        if let Some(result) = result {
+            body.push_str(&format!("\n{}return {}", body_indent, result));
+        }
+
+        let function = format!(
+            "function {}({}) {{\n{}\n{}}}\n\n{}",
+            name,
+            params.join(", "),
+            body,
+            base_indent,
+            base_indent
+        );
+        let range = Range::new(
+            point_to_position(first.start_position()),
+            point_to_position(last.end_position()),
+        );
+        let edits = vec![
+            TextEdit::new(Range::new(position, position), function),
+            TextEdit::new(range, call),
+        ];
+        let action = self.new_action(title, CodeActionKind::REFACTOR_EXTRACT, edits);
+
+        self.actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+
+    fn get_statements_in(&self, range: Range) -> Option<Vec<Node<'a>>> {
+        let start = position_to_point(range.start);
+        let end = position_to_point(range.end);
+        let root = self.document.tree.root_node();
+        let mut scope = root.descendant_for_point_range(start, end)?;
+
+        while !matches!(scope.kind(), "program" | "stmt_block") {
+            scope = scope.parent()?;
+        }
+
+        let mut cursor = scope.walk();
+        let statements: Vec<_> = scope
+            .named_children(&mut cursor)
+            .filter(|node| node.kind() != "comment")
+            .filter(|node| node.end_position() > start && node.start_position() < end)
+            .collect();
+
+        match statements.iter().any(|node| node.has_error()) {
+            true => None,
+            false => Some(statements).filter(|statements| !statements.is_empty()),
+        }
+    }
+
+    /// Checks the control flow leaving the selection, returns `true` when the
+    /// selection ends with a return statement that can be kept as is.
+    fn get_escaping_flow(&self, statements: &[Node<'a>]) -> Result<bool, &'static str> {
+        let mut returns = false;
+
+        for (index, statement) in statements.iter().enumerate() {
+            for node in get_descendants(statement) {
+                match NodeType::from(&node) {
+                    NodeType::StmtReturn if !is_enclosed(&node, statement, &FUNCTION_NODE) => {
+                        if index == statements.len() - 1 && node == *statement {
+                            returns = true;
+                        } else {
+                            return Err("Selection contains a conditional return");
+                        }
+                    }
+                    NodeType::StmtBreak | NodeType::StmtContinue
+                        if !is_enclosed(&node, statement, &LOOP_NODE) =>
+                    {
+                        return Err("Selection contains a break or continue outside of a loop");
+                    }
+                    NodeType::ExprLiteral
+                        if node.child(0).map(|child| child.kind()) == Some("self")
+                            && !is_enclosed(&node, statement, &FUNCTION_NODE) =>
+                    {
+                        return Err("Selection references 'self'");
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(returns)
+    }
+
+    fn get_extracted_params(&self, statements: &[Node<'a>]) -> Vec<String> {
+        let first = statements[0];
+        let last = statements[statements.len() - 1];
+        let mut params: Vec<String> = Vec::new();
+
+        for statement in statements {
+            for node in get_descendants(statement) {
+                if let Some(decl) = self.get_reference(&node) {
+                    let start = position_to_point(decl.name_range.start);
+                    let inside = start >= first.start_position() && start < last.end_position();
+
+                    if !inside && !decl.is_global() && !params.contains(&decl.name) {
+                        params.push(decl.name);
+                    }
+                }
+            }
+        }
+
+        params
+    }
+
+    /// Returns the values modified inside the selection and used afterwards,
+    /// along with whether they are declared inside the selection. Inside a
+    /// loop, the next iterations also use the values read anywhere in it.
+    fn get_extracted_results(&self, statements: &[Node<'a>]) -> Vec<(String, bool)> {
+        let first = statements[0];
+        let last = statements[statements.len() - 1];
+        let mut candidates: Vec<(Declaration, bool)> = Vec::new();
+
+        for statement in statements {
+            for node in get_descendants(statement) {
+                let target = match NodeType::from(&node) {
+                    NodeType::StmtVarDecl | NodeType::StmtFuncDecl if node == *statement => {
+                        node.child_by_field_name("name")
+                    }
+                    _ if is_assignment(&node) && !is_enclosed(&node, statement, &FUNCTION_NODE) => {
+                        node.child_by_field_name("lhs")
+                            .filter(|lhs| NodeType::from(lhs) == NodeType::ExprIdentifier)
+                    }
+                    _ => None,
+                };
+
+                let decl = target.and_then(|target| match NodeType::from(&node) {
+                    NodeType::StmtVarDecl | NodeType::StmtFuncDecl => self
+                        .snapshot
+                        .declarations
+                        .get_by_name_range(self.get_text(&target), get_node_range(&target))
+                        .cloned(),
+                    _ => self.resolve(&target),
+                });
+
+                if let Some(decl) = decl {
+                    let start = position_to_point(decl.name_range.start);
+                    let declared = start >= first.start_position() && start < last.end_position();
+
+                    // the function modifies the globals declared outside in place
+                    if decl.is_global() && !declared {
+                        continue;
+                    }
+
+                    if !candidates.iter().any(|(value, _)| is_same(value, &decl)) {
+                        candidates.push((decl, declared));
+                    }
+                }
+            }
+        }
+
+        let mut results = Vec::new();
+        let root = self.document.tree.root_node();
+        let following: Vec<_> = get_descendants(&root)
+            .into_iter()
+            .filter(|node| node.start_byte() >= last.end_byte())
+            .filter_map(|node| self.get_reference(&node))
+            .collect();
+        let carried: Vec<_> = match get_enclosing_loop(&first) {
+            Some(node) => get_descendants(&node)
+                .into_iter()
+                .filter(|node| !is_overwritten(node))
+                .filter_map(|node| self.get_reference(&node))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        for (decl, declared) in candidates {
+            let is_carried = !declared && carried.iter().any(|value| is_same(value, &decl));
+
+            if is_carried || following.iter().any(|value| is_same(value, &decl)) {
+                results.push((decl.name, declared));
+            }
+        }
+
+        results
+    }
+
+    fn get_expression_at(&self, range: Range) -> Option<Node<'a>> {
+        let start = position_to_point(range.start);
+        let end = position_to_point(range.end);
+        let root = self.document.tree.root_node();
+        let mut node = root.descendant_for_point_range(start, end)?;
+
+        loop {
+            if is_extractable(&node) {
+                return Some(node);
+            }
+
+            if node.kind().starts_with("stmt_") {
+                return None;
+            }
+
+            node = node.parent()?;
+        }
+    }
+
+    fn find_occurrences(&self, expression: &Node<'a>, statement: &Node<'a>) -> Vec<Node<'a>> {
+        let text = self.get_text(expression);
+        let identifiers = self.get_identifiers(expression);
+        let scope = statement.parent().unwrap();
+        let mut occurrences = Vec::new();
+        let mut assignments = Vec::new();
+        let mut stack = Vec::new();
+        let mut cursor = scope.walk();
+
+        for child in scope.named_children(&mut cursor) {
+            if child.start_byte() >= statement.start_byte() {
+                stack.push(child);
+            }
+        }
+
+        stack.reverse();
+
+        while let Some(node) = stack.pop() {
+            if node.kind() == expression.kind() && self.get_text(&node) == text {
+                if is_extractable(&node) {
+                    occurrences.push(node);
+                }
+
+                continue;
+            }
+
+            let reassigned = match NodeType::from(&node) {
+                NodeType::StmtVarDecl => node.child_by_field_name("name"),
+                _ if is_assignment(&node) => node.child_by_field_name("lhs").map(get_root),
+                _ => None,
+            };
+
+            if let Some(target) = reassigned {
+                if identifiers.contains(&self.get_text(&target)) {
+                    assignments.push(node);
+                }
+            }
+
+            let mut cursor = node.walk();
+            let children: Vec<_> = node.named_children(&mut cursor).collect();
+
+            stack.extend(children.into_iter().rev());
+        }
+
+        let limit = assignments
+            .iter()
+            .map(|node| get_reassignment_start(node, statement))
+            .min()
+            .unwrap_or(usize::MAX);
+
+        occurrences
+            .into_iter()
+            .filter(|node| node.start_byte() < limit || node == expression)
+            .collect()
+    }
+}
+
+fn is_extractable(node: &Node) -> bool {
+    let node_type = NodeType::from(node);
+
+    if !node.kind().starts_with("expr_")
+        || node_type == NodeType::ExprIdentifier
+        || node.has_error()
+        || is_assignment(node)
+    {
+        return false;
+    }
+
+    match node.parent() {
+        Some(parent) => match parent.kind() {
+            "stmt_expression" | "literal_pattern" => false,
+            _ => !(is_assignment(&parent) && parent.child_by_field_name("lhs") == Some(*node)),
+        },
+        None => false,
+    }
+}
+
+fn can_extract(expression: &Node, statement: &Node) -> bool {
+    let mut current = expression.parent();
+
+    while let Some(node) = current {
+        if node == *statement {
+            break;
+        }
+
+        if NodeType::from(&node) == NodeType::ExprLambda {
+            return false;
+        }
+
+        current = node.parent();
+    }
+
+    match NodeType::from(statement) {
+        NodeType::StmtWhile => {
+            let condition = statement.child_by_field_name("condition").unwrap();
+
+            expression.end_byte() <= condition.start_byte()
+                || expression.start_byte() >= condition.end_byte()
+        }
+        _ => true,
+    }
+}
+
+/// Checks whether the expression is skipped on some paths of the statement,
+/// such as the right side of `and`/`or`, an `else if` or a match arm.
+fn is_conditional(expression: &Node, statement: &Node) -> bool {
+    let mut current = *expression;
+
+    while current != *statement {
+        let Some(parent) = current.parent() else {
+            return false;
+        };
+        let conditional = match parent.kind() {
+            "expr_binary" => {
+                let operator = parent.child_by_field_name("operator");
+
+                parent.child_by_field_name("rhs") == Some(current)
+                    && operator.is_some_and(|operator| matches!(operator.kind(), "and" | "or"))
+            }
+            // the body is a block, anything else is the `else if` branch
+            "expr_if" => parent.child_by_field_name("condition") != Some(current),
+            "match_arm" | "default_arm" => true,
+            _ => false,
+        };
+
+        if conditional {
+            return true;
+        }
+
+        current = parent;
+    }
+
+    false
+}
+
+fn get_extract_edits(
+    statement: &Node,
+    declaration: &str,
+    name: &str,
+    occurrences: &[Node],
+) -> Vec<TextEdit> {
+    let start = point_to_position(statement.start_position());
+    let mut edits = Vec::new();
+    let mut inserted = false;
+
+    for occurrence in occurrences {
+        let range = get_node_range(occurrence);
+
+        if occurrence.start_byte() == statement.start_byte() {
+            edits.push(TextEdit::new(range, format!("{}{}", declaration, name)));
+            inserted = true;
+        } else {
+            edits.push(TextEdit::new(range, name.to_owned()));
+        }
+    }
+
+    if !inserted {
+        edits.insert(
+            0,
+            TextEdit::new(Range::new(start, start), declaration.to_owned()),
+        );
+    }
+
+    edits
+}
+
+/// Returns the outermost loop containing the node inside its function.
+/// Returns the offset from which the reassignment affects the following code:
+/// the start of the outermost loop repeating it after the statement, or its
+/// end otherwise.
+fn get_reassignment_start(node: &Node, statement: &Node) -> usize {
+    let mut current = node.parent();
+    let mut result = node.end_byte();
+
+    while let Some(value) = current {
+        if value.start_byte() <= statement.start_byte() {
+            break;
+        }
+
+        if LOOP_NODE.contains(&NodeType::from(&value)) {
+            result = value.start_byte();
+        }
+
+        current = value.parent();
+    }
+
+    result
+}
+
+fn get_enclosing_loop<'tree>(node: &Node<'tree>) -> Option<Node<'tree>> {
+    let mut current = node.parent();
+    let mut result = None;
+
+    while let Some(value) = current {
+        let node_type = NodeType::from(&value);
+
+        if FUNCTION_NODE.contains(&node_type) {
+            break;
+        }
+
+        if LOOP_NODE.contains(&node_type) {
+            result = Some(value);
+        }
+
+        current = value.parent();
+    }
+
+    result
+}
+
+/// Checks whether the node is the target of a plain assignment, which
+/// doesn't read its previous value.
+fn is_overwritten(node: &Node) -> bool {
+    node.parent().is_some_and(|parent| {
+        is_assignment(&parent)
+            && parent.child_by_field_name("lhs") == Some(*node)
+            && parent
+                .child_by_field_name("operator")
+                .is_some_and(|operator| operator.kind() == "=")
+    })
+}
+
+/// Checks whether the node has a parent of the given types without leaving
+/// the boundary node.
+fn is_enclosed(node: &Node, boundary: &Node, parent_types: &[NodeType]) -> bool {
+    let mut current = *node;
+
+    while current != *boundary {
+        let Some(parent) = current.parent() else {
+            return false;
+        };
+
+        if parent_types.contains(&NodeType::from(&parent)) {
+            return true;
+        }
+
+        current = parent;
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::code_action::tests::{run, Outcome};
+
+    #[test]
+    fn extract_variable() {
+        let source = "set a = 1\nprint(«a * 2» + 3)\n";
+
+        assert_eq!(
+            run(source, "Extract into variable 'result'"),
+            Outcome::Applied("set a = 1\nset result = a * 2\nprint(result + 3)\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn extract_all_occurrences() {
+        let source = "set a = 1\nprint(«a + 2»)\nprint(a + 2)\n";
+        let expected = "set a = 1\nset result = a + 2\nprint(result)\nprint(result)\n";
+
+        assert_eq!(
+            run(source, "Extract all 2 occurrences into variable 'result'"),
+            Outcome::Applied(expected.to_owned())
+        );
+    }
+
+    #[test]
+    fn extract_occurrences_before_loop() {
+        let source = "set a = 1\nprint(«a + 2»)\nwhile a + 2 < 10 {\n    a = a + 1\n}\n";
+        let loop_body = "set a = 1\nprint(«a + 2»)\nloop {\n    print(a + 2)\n    a = 3\n}\n";
+
+        assert_eq!(
+            run(source, "Extract all 2 occurrences into variable 'result'"),
+            Outcome::Missing
+        );
+        assert_eq!(
+            run(
+                loop_body,
+                "Extract all 2 occurrences into variable 'result'"
+            ),
+            Outcome::Missing
+        );
+    }
+
+    #[test]
+    fn extract_variable_unique_name() {
+        let source = "set field = 1\nset x = { field: 2 }\nprint(«x.field»)\n";
+        let expected = "set field = 1\nset x = { field: 2 }\nset field2 = x.field\nprint(field2)\n";
+
+        assert_eq!(
+            run(source, "Extract into variable 'field2'"),
+            Outcome::Applied(expected.to_owned())
+        );
+    }
+
+    #[test]
+    fn extract_guarded_expression() {
+        let reason = Outcome::Disabled("Expression is only evaluated under a condition".to_owned());
+        let guarded = "set x = null\nif x != null and «x.field» > 0 {\n    print(x)\n}\n";
+        let else_if = "set x = null\nif x == null {\n    print(1)\n} else if «x.field» > 0 {\n    print(x)\n}\n";
+        let arm = "set x = null\nprint(match x {\n    null: 0,\n    _: «x.field» + 1,\n})\n";
+
+        assert_eq!(run(guarded, "Extract into variable 'field'"), reason);
+        assert_eq!(run(else_if, "Extract into variable 'field'"), reason);
+        assert_eq!(run(arm, "Extract into variable 'field'"), reason);
+    }
+
+    #[test]
+    fn extract_guard_condition() {
+        let source = "set x = null\nif «x != null» and x.field > 0 {\n    print(x)\n}\n";
+        let expected =
+            "set x = null\nset result = x != null\nif result and x.field > 0 {\n    print(x)\n}\n";
+
+        assert_eq!(
+            run(source, "Extract into variable 'result'"),
+            Outcome::Applied(expected.to_owned())
+        );
+    }
+
+    #[test]
+    fn extract_function() {
+        let source = "function f(a) {\n    «set b = a * 2\n    print(b)»\n    return b\n}\n";
+        let expected = "function extracted(a) {\n    set b = a * 2\n    print(b)\n    return b\n}\n\nfunction f(a) {\n    set b = extracted(a)\n    return b\n}\n";
+
+        assert_eq!(
+            run(source, "Extract into function 'extracted'"),
+            Outcome::Applied(expected.to_owned())
+        );
+    }
+
+    #[test]
+    fn extract_loop_accumulator() {
+        let source = "function f(xs) {\n    set total = 0\n    for x in xs {\n        print(total)\n        «total = total + x»\n    }\n}\n";
+        let expected = "function extracted(total, x) {\n    total = total + x\n    return total\n}\n\nfunction f(xs) {\n    set total = 0\n    for x in xs {\n        print(total)\n        total = extracted(total, x)\n    }\n}\n";
+
+        assert_eq!(
+            run(source, "Extract into function 'extracted'"),
+            Outcome::Applied(expected.to_owned())
+        );
+    }
+
+    #[test]
+    fn extract_global_declaration() {
+        let source = "«set a = [1, 2]»\nprint(a)\n";
+        let expected =
+            "function extracted() {\n    set a = [1, 2]\n    return a\n}\n\nset a = extracted()\nprint(a)\n";
+        let assigned = "set a = 1\n«a = 2»\nprint(a)\n";
+        let expected_assigned =
+            "set a = 1\nfunction extracted() {\n    a = 2\n}\n\nextracted()\nprint(a)\n";
+
+        assert_eq!(
+            run(source, "Extract into function 'extracted'"),
+            Outcome::Applied(expected.to_owned())
+        );
+        assert_eq!(
+            run(assigned, "Extract into function 'extracted'"),
+            Outcome::Applied(expected_assigned.to_owned())
+        );
+    }
+
+    #[test]
+    fn extract_function_escaping_flow() {
+        let conditional =
+            "function f(a) {\n    «if a {\n        return 1\n    }\n    print(a)»\n}\n";
+        let jump =
+            "function f(xs) {\n    for x in xs {\n        «print(x)\n        break»\n    }\n}\n";
+
+        assert_eq!(
+            run(conditional, "Extract into function 'extracted'"),
+            Outcome::Disabled("Selection contains a conditional return".to_owned())
+        );
+        assert_eq!(
+            run(jump, "Extract into function 'extracted'"),
+            Outcome::Disabled(
+                "Selection contains a break or continue outside of a loop".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn extract_function_several_results() {
+        let source = "function f() {\n    «set a = 1\n    set b = 2»\n    print(a, b)\n}\n";
+
+        assert_eq!(
+            run(source, "Extract into function 'extracted'"),
+            Outcome::Disabled("Selection assigns more than one value used afterwards".to_owned())
+        );
+    }
+
+    #[test]
+    fn extract_from_lambda() {
+        let source = "set f = lambda(a) «a + 1»\n";
+
+        assert_eq!(
+            run(source, "Extract into variable 'result'"),
+            Outcome::Missing
+        );
+    }
+}
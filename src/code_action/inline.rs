@@ -0,0 +1,517 @@
+use tower_lsp::lsp_types::{CodeActionKind, CodeActionOrCommand, Range, TextEdit};
+use tree_sitter::Node;
+
+use crate::{
+    analyzer::Identifier,
+    ast::{has_side_effects, is_assignment, needs_parentheses, NodeType, FUNCTION_NODE, LOOP_NODE},
+    declarations::Declaration,
+    utils::{get_node_range, position_to_point},
+};
+
+use super::{get_descendants, get_root, get_statement, is_same, reindent, CodeActionProvider};
+
+impl<'a> CodeActionProvider<'a> {
+    pub(super) fn inline(&mut self, range: Range) {
+        let Some(node) = self.get_node_at(Range::new(range.start, range.start)) else {
+            return;
+        };
+
+        if NodeType::from(&node) != NodeType::ExprIdentifier {
+            return;
+        }
+
+        let call = node
+            .parent()
+            .filter(|parent| NodeType::from(parent) == NodeType::ExprCall)
+            .filter(|parent| parent.child_by_field_name("func") == Some(node));
+
+        if let Some(call) = call {
+            self.inline_function(&node, &call);
+        }
+
+        self.inline_variable(&node);
+    }
+
+    fn inline_variable(&mut self, node: &Node) {
+        let Some((decl, decl_node)) = self.get_declaration(node) else {
+            return;
+        };
+
+        if NodeType::from(&decl_node) != NodeType::StmtVarDecl {
+            return;
+        }
+
+        let title = format!("Inline variable '{}'", decl.name);
+        let value = decl_node.child_by_field_name("value").unwrap();
+        let references = self.get_references(&decl);
+
+        if let Err(reason) = self.check_inline_variable(&decl, &decl_node, &references) {
+            self.push_disabled(title, CodeActionKind::REFACTOR_INLINE, &reason);
+            return;
+        }
+
+        let text = self.get_text(&value);
+        let from = self.get_indent(&decl_node);
+        let mut edits = vec![TextEdit::new(
+            self.get_removal_range(&decl_node),
+            String::new(),
+        )];
+
+        for reference in references {
+            let to = get_statement(&reference)
+                .map(|statement| self.get_indent(&statement))
+                .unwrap_or_default();
+            let text = reindent(text, from, to);
+            let text = match needs_parentheses(&value, &reference) {
+                true => format!("({})", text),
+                false => text,
+            };
+
+            edits.push(TextEdit::new(get_node_range(&reference), text));
+        }
+
+        let action = self.new_action(title, CodeActionKind::REFACTOR_INLINE, edits);
+
+        self.actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+
+    fn check_inline_variable(
+        &self,
+        decl: &Declaration,
+        decl_node: &Node,
+        references: &[Node],
+    ) -> Result<(), String> {
+        let value = decl_node.child_by_field_name("value").unwrap();
+
+        if has_side_effects(&value) {
+            return Err(format!("The value of '{}' has side effects", decl.name));
+        }
+
+        if self.is_reassigned(decl, decl_node.end_byte(), usize::MAX) {
+            return Err(format!("'{}' is reassigned", decl.name));
+        }
+
+        if references.len() > 1 && is_reference_literal(&value) {
+            return Err(format!(
+                "The value of '{}' would no longer be shared between its uses",
+                decl.name
+            ));
+        }
+
+        let uses_end = references
+            .iter()
+            .map(|node| get_use_end(node, decl_node))
+            .max()
+            .unwrap_or(0);
+
+        for node in get_descendants(&value) {
+            let Some(captured) = self.get_reference(&node) else {
+                continue;
+            };
+
+            if is_inside(&captured, &value) {
+                continue;
+            }
+
+            if self.is_reassigned(&captured, decl_node.end_byte(), uses_end) {
+                return Err(format!("'{}' is modified before a use", captured.name));
+            }
+
+            if !references
+                .iter()
+                .all(|reference| self.is_visible_at(&captured, reference))
+            {
+                return Err(format!("'{}' is shadowed at a use site", captured.name));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn inline_function(&mut self, node: &Node, call: &Node) {
+        let Some(decl) = self.get_reference(node) else {
+            return;
+        };
+        let Some((_, decl_node)) = self.get_declaration_node(&decl) else {
+            return;
+        };
+
+        let function = match NodeType::from(&decl_node) {
+            NodeType::StmtFuncDecl => decl_node,
+            NodeType::StmtVarDecl => match decl_node.child_by_field_name("value") {
+                Some(value) if NodeType::from(&value) == NodeType::ExprLambda => value,
+                _ => return,
+            },
+            _ => return,
+        };
+
+        let title = format!("Inline function '{}'", decl.name);
+
+        match self.get_inlined_call(&decl, &decl_node, &function, call) {
+            Ok(text) => {
+                let edit = TextEdit::new(get_node_range(call), text);
+                let action = self.new_action(title, CodeActionKind::REFACTOR_INLINE, vec![edit]);
+
+                self.actions.push(CodeActionOrCommand::CodeAction(action));
+            }
+            Err(reason) => self.push_disabled(title, CodeActionKind::REFACTOR_INLINE, &reason),
+        }
+    }
+
+    fn get_inlined_call(
+        &self,
+        decl: &Declaration,
+        decl_node: &Node,
+        function: &Node,
+        call: &Node,
+    ) -> Result<String, String> {
+        if NodeType::from(decl_node) == NodeType::StmtVarDecl
+            && self.is_reassigned(decl, decl_node.end_byte(), usize::MAX)
+        {
+            return Err(format!("'{}' is reassigned", decl.name));
+        }
+
+        let body = function.child_by_field_name("body").unwrap();
+        let Some(expression) = get_single_expression(&body) else {
+            return Err(format!(
+                "'{}' is not a single expression function",
+                decl.name
+            ));
+        };
+
+        let params_node = function.child_by_field_name("args").unwrap();
+        let args_node = call.child_by_field_name("args").unwrap();
+        let mut cursor = params_node.walk();
+        let params: Vec<_> = params_node
+            .named_children(&mut cursor)
+            .filter(|node| node.kind() != "comment")
+            .collect();
+        let mut cursor = args_node.walk();
+        let args: Vec<_> = args_node
+            .named_children(&mut cursor)
+            .filter(|node| node.kind() != "comment")
+            .collect();
+
+        if params.len() != args.len() {
+            return Err(format!(
+                "'{}' expects {} arguments but got {}",
+                decl.name,
+                params.len(),
+                args.len()
+            ));
+        }
+
+        let mut replacements = Vec::new();
+        let mut counts = vec![0; params.len()];
+
+        for node in get_descendants(&expression) {
+            if node.kind() == "self" && !has_function_parent(&node, &expression) {
+                return Err(format!("'{}' references 'self'", decl.name));
+            }
+
+            let Some(reference) = self.get_reference(&node) else {
+                continue;
+            };
+
+            let param = params
+                .iter()
+                .position(|param| get_node_range(param) == reference.name_range);
+
+            match param {
+                Some(index) => {
+                    let arg = &args[index];
+                    let text = match needs_parentheses(arg, &node) {
+                        true => format!("({})", self.get_text(arg)),
+                        false => self.get_text(arg).to_owned(),
+                    };
+
+                    counts[index] += 1;
+                    replacements.push((node, text));
+                }
+                None if !self.is_visible_at(&reference, call) => {
+                    return Err(format!("'{}' is shadowed at the call site", reference.name));
+                }
+                None => {}
+            }
+        }
+
+        for (index, arg) in args.iter().enumerate() {
+            if counts[index] != 1 && has_side_effects(arg) {
+                return Err(format!(
+                    "Argument '{}' has side effects and is used {} times",
+                    self.get_text(arg),
+                    counts[index]
+                ));
+            }
+        }
+
+        let offset = expression.start_byte();
+        let mut text = self.get_text(&expression).to_owned();
+
+        for (node, value) in replacements.iter().rev() {
+            text.replace_range(node.start_byte() - offset..node.end_byte() - offset, value);
+        }
+
+        match needs_parentheses(&expression, call) {
+            true => Ok(format!("({})", text)),
+            false => Ok(text),
+        }
+    }
+
+    pub(super) fn get_declaration(&self, node: &Node) -> Option<(Declaration, Node<'a>)> {
+        let parent = node.parent()?;
+        let decl = match NodeType::from(&parent) {
+            NodeType::StmtVarDecl | NodeType::StmtFuncDecl
+                if parent.child_by_field_name("name") == Some(*node) =>
+            {
+                self.snapshot
+                    .declarations
+                    .get_by_name_range(self.get_text(node), get_node_range(node))
+                    .cloned()?
+            }
+            _ => self.get_reference(node)?,
+        };
+
+        self.get_declaration_node(&decl)
+    }
+
+    fn get_declaration_node(&self, decl: &Declaration) -> Option<(Declaration, Node<'a>)> {
+        let start = position_to_point(decl.name_range.start);
+        let end = position_to_point(decl.name_range.end);
+        let root = self.document.tree.root_node();
+        let name = root.descendant_for_point_range(start, end)?;
+        let parent = name.parent()?;
+
+        match parent.child_by_field_name("name") == Some(name) {
+            true => Some((decl.clone(), parent)),
+            false => None,
+        }
+    }
+
+    pub(super) fn get_references(&self, decl: &Declaration) -> Vec<Node<'a>> {
+        get_descendants(&self.document.tree.root_node())
+            .into_iter()
+            .filter(|node| {
+                self.get_reference(node)
+                    .is_some_and(|value| is_same(&value, decl))
+            })
+            .collect()
+    }
+
+    /// Checks whether a declaration is assigned or mutated between two offsets.
+    pub(super) fn is_reassigned(&self, decl: &Declaration, start: usize, end: usize) -> bool {
+        get_descendants(&self.document.tree.root_node())
+            .into_iter()
+            .filter(|node| is_assignment(node))
+            .filter(|node| node.start_byte() >= start && node.start_byte() < end)
+            .filter_map(|node| node.child_by_field_name("lhs"))
+            .filter_map(|lhs| self.get_reference(&get_root(lhs)))
+            .any(|value| is_same(&value, decl))
+    }
+
+    fn is_visible_at(&self, decl: &Declaration, node: &Node) -> bool {
+        let identifier = Identifier {
+            name: decl.name.clone(),
+            range: get_node_range(node),
+        };
+
+        self.snapshot
+            .declarations
+            .get(&identifier)
+            .is_some_and(|value| is_same(value, decl))
+    }
+}
+
+fn get_single_expression<'tree>(body: &Node<'tree>) -> Option<Node<'tree>> {
+    let statement = match NodeType::from(body) {
+        NodeType::StmtBlock => {
+            let mut cursor = body.walk();
+            let statements: Vec<_> = body
+                .named_children(&mut cursor)
+                .filter(|node| node.kind() != "comment")
+                .collect();
+
+            match statements.as_slice() {
+                [statement] => *statement,
+                _ => return None,
+            }
+        }
+        _ => *body,
+    };
+
+    match NodeType::from(&statement) {
+        NodeType::StmtExpression => statement.named_child(0),
+        NodeType::StmtReturn => statement.child_by_field_name("value"),
+        _ => None,
+    }
+}
+
+fn has_function_parent(node: &Node, boundary: &Node) -> bool {
+    let mut current = node.parent();
+
+    while let Some(value) = current {
+        if FUNCTION_NODE.contains(&NodeType::from(&value)) {
+            return true;
+        }
+
+        if value == *boundary {
+            return false;
+        }
+
+        current = value.parent();
+    }
+
+    false
+}
+
+/// Checks whether the value creates a new array, object or function each
+/// time it is evaluated.
+fn is_reference_literal(node: &Node) -> bool {
+    match NodeType::from(node) {
+        NodeType::ExprArray | NodeType::ExprObject | NodeType::ExprLambda => true,
+        NodeType::ExprGroup => node
+            .named_child(0)
+            .is_some_and(|child| is_reference_literal(&child)),
+        _ => false,
+    }
+}
+
+/// Returns the offset up to which the captured values must stay unchanged for
+/// the use: the end of the outermost loop repeating it without the
+/// declaration, or the use itself.
+fn get_use_end(node: &Node, decl_node: &Node) -> usize {
+    let mut current = node.parent();
+    let mut result = node.start_byte();
+
+    while let Some(value) = current {
+        if value.start_byte() <= decl_node.start_byte() {
+            break;
+        }
+
+        if LOOP_NODE.contains(&NodeType::from(&value)) {
+            result = value.end_byte();
+        }
+
+        current = value.parent();
+    }
+
+    result
+}
+
+fn is_inside(decl: &Declaration, node: &Node) -> bool {
+    let start = position_to_point(decl.name_range.start);
+
+    start >= node.start_position() && start < node.end_position()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::code_action::tests::{run, Outcome};
+
+    #[test]
+    fn inline_variable() {
+        let source = "set a = 1\nset «b» = a + 2\nprint(b * 3)\nprint(b)\n";
+
+        assert_eq!(
+            run(source, "Inline variable 'b'"),
+            Outcome::Applied("set a = 1\nprint((a + 2) * 3)\nprint(a + 2)\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn inline_shared_value() {
+        let array = "set xs = []\npush(«xs», 1)\nprint(xs)\n";
+        let object = "set o = { a: 1 }\nprint(«o»)\nprint(o)\n";
+        let lambda = "set f = (lambda(x) x)\nprint(«f»)\nprint(f)\n";
+
+        assert_eq!(
+            run(array, "Inline variable 'xs'"),
+            Outcome::Disabled(
+                "The value of 'xs' would no longer be shared between its uses".to_owned()
+            )
+        );
+        assert_eq!(
+            run(object, "Inline variable 'o'"),
+            Outcome::Disabled(
+                "The value of 'o' would no longer be shared between its uses".to_owned()
+            )
+        );
+        assert_eq!(
+            run(lambda, "Inline variable 'f'"),
+            Outcome::Disabled(
+                "The value of 'f' would no longer be shared between its uses".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn inline_single_use_array() {
+        let source = "set xs = [1, 2]\nprint(«xs»)\n";
+
+        assert_eq!(
+            run(source, "Inline variable 'xs'"),
+            Outcome::Applied("print([1, 2])\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn inline_reassigned_variable() {
+        let reassigned = "set «a» = 1\na = 2\nprint(a)\n";
+        let side_effects = "set «a» = input()\nprint(a)\n";
+        let modified = "set b = 1\nset «a» = b + 1\nb = 5\nprint(a)\n";
+        let array = "set «xs» = [1]\nxs = [2]\nprint(xs)\n";
+        let looped = "set a = 1\nset «b» = a\nloop {\n    print(b)\n    a = 2\n}\n";
+
+        assert_eq!(
+            run(reassigned, "Inline variable 'a'"),
+            Outcome::Disabled("'a' is reassigned".to_owned())
+        );
+        assert_eq!(
+            run(side_effects, "Inline variable 'a'"),
+            Outcome::Disabled("The value of 'a' has side effects".to_owned())
+        );
+        assert_eq!(
+            run(modified, "Inline variable 'a'"),
+            Outcome::Disabled("'b' is modified before a use".to_owned())
+        );
+        assert_eq!(
+            run(array, "Inline variable 'xs'"),
+            Outcome::Disabled("'xs' is reassigned".to_owned())
+        );
+        assert_eq!(
+            run(looped, "Inline variable 'b'"),
+            Outcome::Disabled("'a' is modified before a use".to_owned())
+        );
+    }
+
+    #[test]
+    fn inline_function() {
+        let source = "function add(a, b) {\n    return a + b\n}\n\nprint(«add»(1, 2) * 3)\n";
+        let expected = "function add(a, b) {\n    return a + b\n}\n\nprint((1 + 2) * 3)\n";
+
+        assert_eq!(
+            run(source, "Inline function 'add'"),
+            Outcome::Applied(expected.to_owned())
+        );
+    }
+
+    #[test]
+    fn inline_function_refused() {
+        let arguments = "function add(a, b) {\n    return a + b\n}\n\nprint(«add»(1))\n";
+        let statements = "function f(a) {\n    print(a)\n    return a\n}\n\nprint(«f»(1))\n";
+        let duplicated = "function twice(a) {\n    return a + a\n}\n\nprint(«twice»(input()))\n";
+
+        assert_eq!(
+            run(arguments, "Inline function 'add'"),
+            Outcome::Disabled("'add' expects 2 arguments but got 1".to_owned())
+        );
+        assert_eq!(
+            run(statements, "Inline function 'f'"),
+            Outcome::Disabled("'f' is not a single expression function".to_owned())
+        );
+        assert_eq!(
+            run(duplicated, "Inline function 'twice'"),
+            Outcome::Disabled("Argument 'input()' has side effects and is used 2 times".to_owned())
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use tower_lsp::lsp_types::{CodeActionKind, CodeActionOrCommand, Diagnostic, Range, TextEdit};
+
+use crate::{
+    lint::{get_simplification, Lint},
+    utils::get_node_range,
+};
+
+use super::CodeActionProvider;
+
+impl<'a> CodeActionProvider<'a> {
+    pub(super) fn fix_simplification(
+        &mut self,
+        diagnostic: &Diagnostic,
+        lint: Lint,
+        replacement: String,
+    ) {
+        let title = lint.title().to_owned();
+        let edit = TextEdit::new(diagnostic.range, replacement);
+        let mut action = self.new_action(title, CodeActionKind::QUICKFIX, vec![edit]);
+
+        action.diagnostics = Some(vec![diagnostic.clone()]);
+        action.is_preferred = Some(true);
+
+        self.actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+
+    pub(super) fn simplify(&mut self, range: Range) {
+        let source = self.document.content.as_bytes();
+        let mut current = self.get_node_at(range);
+
+        while let Some(node) = current {
+            if node.kind().starts_with("stmt_") {
+                break;
+            }
+
+            if let Some((lint, replacement)) = get_simplification(&node, source) {
+                let title = lint.title().to_owned();
+                let edit = TextEdit::new(get_node_range(&node), replacement);
+                let action = self.new_action(title, CodeActionKind::REFACTOR_REWRITE, vec![edit]);
+
+                self.actions.push(CodeActionOrCommand::CodeAction(action));
+                break;
+            }
+
+            current = node.parent();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::code_action::tests::{run, Outcome};
+
+    fn simplify(source: &str, title: &str) -> Outcome {
+        run(&format!("set a = 1\nset b = 2\n{}\n", source), title)
+    }
+
+    fn applied(source: &str) -> Outcome {
+        Outcome::Applied(format!("set a = 1\nset b = 2\n{}\n", source))
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let title = "Use compound assignment";
+
+        assert_eq!(simplify("«a = a * 2»", title), applied("a *= 2"));
+        assert_eq!(simplify("«a = b * 2»", title), Outcome::Missing);
+        assert_eq!(simplify("«a = a and b»", title), Outcome::Missing);
+    }
+
+    #[test]
+    fn negated_comparison() {
+        let title = "Invert comparison";
+
+        assert_eq!(
+            simplify("print(«!(a == b)»)", title),
+            applied("print(a != b)")
+        );
+        assert_eq!(simplify("print(«!(a > b)»)", title), Outcome::Missing);
+    }
+
+    #[test]
+    fn redundant_if() {
+        let title = "Replace if expression with its condition";
+
+        assert_eq!(
+            simplify("print(«if a > b { true } else { false }»)", title),
+            applied("print(a > b)")
+        );
+        assert_eq!(
+            simplify("print(«if a > b { false } else { true }»)", title),
+            applied("print(!(a > b))")
+        );
+        assert_eq!(
+            simplify("print(«if a > b { true } else { true }»)", title),
+            Outcome::Missing
+        );
+    }
+
+    #[test]
+    fn bool_comparison() {
+        let title = "Remove comparison with boolean literal";
+
+        assert_eq!(simplify("print(«a == true»)", title), applied("print(a)"));
+        assert_eq!(simplify("print(«false == a»)", title), applied("print(!a)"));
+        assert_eq!(simplify("print(«a != true»)", title), applied("print(!a)"));
+    }
+}
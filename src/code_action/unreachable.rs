@@ -0,0 +1,134 @@
+use tower_lsp::lsp_types::{CodeActionKind, CodeActionOrCommand, Diagnostic, Range, TextEdit};
+
+use crate::{
+    diagnostic::DiagnosticData,
+    utils::{point_to_position, position_to_point},
+};
+
+use super::{CodeActionProvider, REMOVE_UNREACHABLE};
+
+impl<'a> CodeActionProvider<'a> {
+    pub(super) fn remove_unreachable(&mut self, diagnostic: &Diagnostic) {
+        let Some(range) = self.get_unreachable_range(diagnostic.range) else {
+            return;
+        };
+
+        let title = "Remove unreachable code".to_owned();
+        let edit = TextEdit::new(range, String::new());
+        let mut action = self.new_action(title, CodeActionKind::QUICKFIX, vec![edit]);
+
+        action.diagnostics = Some(vec![diagnostic.clone()]);
+        action.is_preferred = Some(true);
+
+        self.actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+
+    pub(super) fn remove_all_unreachable(&mut self) {
+        let mut ranges: Vec<Range> = self
+            .snapshot
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| {
+                matches!(
+                    DiagnosticData::from_diagnostic(diagnostic),
+                    Some(DiagnosticData::Unreachable)
+                )
+            })
+            .filter_map(|diagnostic| self.get_unreachable_range(diagnostic.range))
+            .collect();
+
+        ranges.sort_by_key(|range| (range.start, std::cmp::Reverse(range.end)));
+        ranges.dedup_by(|range, outer| range.start >= outer.start && range.end <= outer.end);
+
+        if ranges.is_empty() {
+            return;
+        }
+
+        let title = "Remove all unreachable code".to_owned();
+        let edits = ranges
+            .into_iter()
+            .map(|range| TextEdit::new(range, String::new()))
+            .collect();
+        let action = self.new_action(title, REMOVE_UNREACHABLE.clone(), edits);
+
+        self.actions.push(CodeActionOrCommand::CodeAction(action));
+    }
+
+    /// Extends the range of the unreachable statements to the end of the
+    /// statement jumping over them, so that no blank lines are left behind.
+    fn get_unreachable_range(&self, range: Range) -> Option<Range> {
+        let start = position_to_point(range.start);
+        let root = self.document.tree.root_node();
+        let mut node = root.descendant_for_point_range(start, start)?;
+
+        while let Some(parent) = node.parent() {
+            if matches!(parent.kind(), "program" | "stmt_block") {
+                break;
+            }
+
+            node = parent;
+        }
+
+        if node.start_position() != start {
+            return None;
+        }
+
+        let previous = node.prev_sibling()?;
+
+        Some(Range::new(
+            point_to_position(previous.end_position()),
+            range.end,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::code_action::tests::{run, Outcome};
+
+    #[test]
+    fn remove_unreachable() {
+        let source = "function f() {\n    return 1\n    «print(2)»\n    print(3)\n}\n";
+        let reachable = "function f() {\n    «print(2)»\n    return 1\n}\n";
+
+        assert_eq!(
+            run(source, "Remove unreachable code"),
+            Outcome::Applied("function f() {\n    return 1\n}\n".to_owned())
+        );
+        assert_eq!(run(reachable, "Remove unreachable code"), Outcome::Missing);
+    }
+
+    #[test]
+    fn remove_all_unreachable() {
+        let source = concat!(
+            "«loop {\n",
+            "    break\n",
+            "    print(1)\n",
+            "}»\n",
+            "function f() {\n",
+            "    if true {\n",
+            "        return 1\n",
+            "        print(2)\n",
+            "    }\n",
+            "    return 2\n",
+            "    print(3)\n",
+            "}\n",
+        );
+        let expected = concat!(
+            "loop {\n",
+            "    break\n",
+            "}\n",
+            "function f() {\n",
+            "    if true {\n",
+            "        return 1\n",
+            "    }\n",
+            "    return 2\n",
+            "}\n",
+        );
+
+        assert_eq!(
+            run(source, "Remove all unreachable code"),
+            Outcome::Applied(expected.to_owned())
+        );
+    }
+}
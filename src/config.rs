@@ -0,0 +1,199 @@
+use std::{
+    fs,
+    ops::Range as Span,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use toml::Spanned;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
+
+use crate::{
+    document::PositionEncoding, formatter::FormatOptions, lint::LintConfig, settings::Settings,
+};
+
+pub const CONFIG_FILE: &str = "icelang.toml";
+
+/// Latest icelang version understood by the server, as `(major, minor)`.
+pub const LANGUAGE_VERSION: (u64, u64) = (0, 1);
+
+/// Project configuration read from the `icelang.toml` file of a project,
+/// takes precedence over the editor settings.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Target icelang version, `major.minor`.
+    pub version: Option<Spanned<String>>,
+    /// Replaces the lint levels of the editor settings.
+    pub lints: Option<LintConfig>,
+    pub format: Option<FormatOptions>,
+    /// Directories searched for the imported modules before the ones of the
+    /// editor settings, relative to the project file.
+    pub module_paths: Vec<Spanned<PathBuf>>,
+    /// Files and directories which aren't indexed, relative to the project
+    /// file.
+    pub ignore: Vec<PathBuf>,
+}
+
+impl ProjectConfig {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|err| err.to_string())?;
+
+        toml::from_str(&content).map_err(|err| err.to_string())
+    }
+
+    /// Looks for the configuration file in the directory and its ancestors.
+    pub fn find(directory: &Path) -> Option<PathBuf> {
+        directory
+            .ancestors()
+            .map(|directory| directory.join(CONFIG_FILE))
+            .find(|path| path.is_file())
+    }
+}
+
+/// A project file with the problems found while reading it, an invalid file
+/// gives the default configuration.
+#[derive(Debug, Clone)]
+pub struct Project {
+    pub path: PathBuf,
+    pub config: ProjectConfig,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Project {
+    /// Reads the project file, `None` when it doesn't exist anymore.
+    pub fn load(path: &Path, encoding: PositionEncoding) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        let directory = path.parent()?;
+        let range = |span: Span<usize>| span_to_range(&content, span, encoding);
+        let mut diagnostics = Vec::new();
+        let mut config = match toml::from_str::<ProjectConfig>(&content) {
+            Ok(config) => config,
+            Err(err) => {
+                let range = range(err.span().unwrap_or(0..0));
+
+                diagnostics.push(diagnostic(range, err.message().to_owned()));
+                ProjectConfig::default()
+            }
+        };
+
+        if let Some(version) = &config.version {
+            match parse_version(version.get_ref()) {
+                Some(target) if target > LANGUAGE_VERSION => {
+                    let message = format!(
+                        "icelang {} is not supported, the latest supported version is {}.{}",
+                        version.get_ref(),
+                        LANGUAGE_VERSION.0,
+                        LANGUAGE_VERSION.1
+                    );
+
+                    diagnostics.push(Diagnostic {
+                        severity: Some(DiagnosticSeverity::WARNING),
+                        ..diagnostic(range(version.span()), message)
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    let message = format!(
+                        "Invalid version '{}', expected 'major.minor'",
+                        version.get_ref()
+                    );
+
+                    diagnostics.push(diagnostic(range(version.span()), message));
+                }
+            }
+        }
+
+        for module_path in &mut config.module_paths {
+            let path = directory.join(module_path.get_ref());
+
+            if !path.is_dir() {
+                let message = format!("Module path '{}' is not a directory", path.display());
+
+                diagnostics.push(Diagnostic {
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    ..diagnostic(range(module_path.span()), message)
+                });
+            }
+
+            *module_path.get_mut() = path;
+        }
+
+        for ignored in &mut config.ignore {
+            *ignored = directory.join(&ignored);
+        }
+
+        Some(Self {
+            path: path.to_owned(),
+            config,
+            diagnostics,
+        })
+    }
+
+    /// Directory of the project file, the project applies to the files below.
+    pub fn directory(&self) -> &Path {
+        self.path.parent().unwrap_or(&self.path)
+    }
+
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        self.config
+            .ignore
+            .iter()
+            .any(|ignored| path.starts_with(ignored))
+    }
+
+    /// Overrides the editor settings with the project configuration.
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(lints) = &self.config.lints {
+            settings.lints = lints.clone();
+        }
+
+        if let Some(format) = &self.config.format {
+            settings.format = Some(format.clone());
+        }
+
+        let module_paths = self.config.module_paths.iter().map(|path| path.get_ref());
+
+        settings.module_paths = module_paths
+            .cloned()
+            .chain(settings.module_paths.drain(..))
+            .collect();
+    }
+}
+
+/// Parses `major.minor` or `major.minor.patch`, the patch is ignored.
+fn parse_version(version: &str) -> Option<(u64, u64)> {
+    let mut parts = version.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+
+    match parts.next().map(str::parse::<u64>) {
+        None | Some(Ok(_)) if parts.next().is_none() => Some((major, minor)),
+        _ => None,
+    }
+}
+
+fn span_to_range(content: &str, span: Span<usize>, encoding: PositionEncoding) -> Range {
+    Range::new(
+        offset_to_position(content, span.start, encoding),
+        offset_to_position(content, span.end, encoding),
+    )
+}
+
+fn offset_to_position(content: &str, offset: usize, encoding: PositionEncoding) -> Position {
+    let before = content.get(..offset).unwrap_or(content);
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    let line = before.matches('\n').count();
+
+    Position::new(line as u32, encoding.text_len(&before[line_start..]) as u32)
+}
+
+fn diagnostic(range: Range, message: String) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("icelang_ls".to_owned()),
+        message,
+        ..Default::default()
+    }
+}
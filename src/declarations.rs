@@ -39,12 +39,19 @@ impl Declaration {
     }
 }
 
+impl Declaration {
+    pub fn is_builtin(&self) -> bool {
+        self.builtin
+    }
+
+    pub fn is_global(&self) -> bool {
+        self.scope.is_none()
+    }
+}
+
 impl DeclarationKind {
     pub fn is_function(&self) -> bool {
-        match self {
-            DeclarationKind::Function(_) => true,
-            _ => false,
-        }
+        matches!(self, DeclarationKind::Function(_))
     }
 }
 
@@ -114,6 +121,12 @@ pub struct DeclarationMap {
     map: HashMap<String, Vec<Declaration>>,
 }
 
+impl Default for DeclarationMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DeclarationMap {
     pub fn new() -> Self {
         let mut map = HashMap::new();
@@ -144,7 +157,7 @@ impl DeclarationMap {
         true
     }
 
-    pub fn get(&mut self, identifer: &Identifier) -> Option<&Declaration> {
+    pub fn get(&self, identifer: &Identifier) -> Option<&Declaration> {
         if let Some(declarations) = self.map.get(&identifer.name) {
             for decl in declarations {
                 if is_declaration_at(decl, identifer.range.end) {
@@ -168,6 +181,21 @@ impl DeclarationMap {
         None
     }
 
+    /// Returns the innermost declaration visible from the identifier, the
+    /// one shadowing the others of the same name.
+    pub fn resolve(&self, identifier: &Identifier) -> Option<Declaration> {
+        let declarations = self.map.get(&identifier.name)?;
+
+        self.get_nearest(declarations, identifier.range.end)
+    }
+
+    pub fn get_by_name_range(&self, name: &str, name_range: Range) -> Option<&Declaration> {
+        self.map
+            .get(name)?
+            .iter()
+            .find(|decl| decl.name_range == name_range)
+    }
+
     pub fn get_declared_at(&self, position: Position) -> Vec<Declaration> {
         let mut result = Vec::new();
 
@@ -231,3 +259,59 @@ fn is_declaration_at(decl: &Declaration, position: Position) -> bool {
 
     condition && inside_scope
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tower_lsp::lsp_types::{DiagnosticSeverity, Url};
+
+    use super::*;
+    use crate::{
+        document::{Document, PositionEncoding, Snapshot},
+        settings::Settings,
+    };
+
+    fn analyze(source: &str) -> Snapshot {
+        let uri = Url::parse("file:///test.ic").unwrap();
+        let document = Document::new(0, source.to_owned(), PositionEncoding::Utf8).unwrap();
+
+        Snapshot::analyze(Arc::new(document), &uri, &Settings::default(), &|_| None)
+    }
+
+    fn get_names(snapshot: &Snapshot, position: Position) -> Vec<String> {
+        let declarations = snapshot.declarations.get_declared_at(position);
+
+        declarations
+            .into_iter()
+            .filter(|decl| !decl.is_builtin())
+            .map(|decl| decl.name)
+            .collect()
+    }
+
+    #[test]
+    fn identifier_ending_the_scope() {
+        let snapshot = analyze("set f = lambda(x) x");
+        let identifier = Identifier {
+            name: "x".to_owned(),
+            range: Range::new(Position::new(0, 18), Position::new(0, 19)),
+        };
+
+        assert!(snapshot.declarations.get(&identifier).is_some());
+        assert!(snapshot
+            .diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.severity != Some(DiagnosticSeverity::ERROR)));
+    }
+
+    #[test]
+    fn declarations_after_the_scope() {
+        let snapshot = analyze("function f(a) { set b = a }\n");
+        let mut names = get_names(&snapshot, Position::new(0, 26));
+
+        names.sort();
+
+        assert_eq!(names, ["a", "b", "f", "self"]);
+        assert_eq!(get_names(&snapshot, Position::new(0, 27)), ["f"]);
+    }
+}
@@ -1,4 +1,9 @@
-use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, DiagnosticTag, Range};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, DiagnosticTag, NumberOrString, Range};
+
+use crate::lint::Lint;
 
 #[derive(Debug, Clone)]
 pub enum ErrorKind {
@@ -9,30 +14,57 @@ pub enum ErrorKind {
     Missing(String),
     UndelimitedStr,
     Redeclaration(String),
-    Undeclared(String),
+    Undeclared(String, Vec<String>),
     ContinueOutside,
     BreakOutside,
     ReturnOutside,
     InvalidName,
+    ModuleNotFound(String),
+    NotExported(String, String),
 }
 
-impl ToString for ErrorKind {
-    fn to_string(&self) -> String {
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ErrorKind::SyntaxError => "Syntax error".to_owned(),
-            ErrorKind::Unexpected => "Unexpected token".to_owned(),
-            ErrorKind::ExpectedExpr => "Expected expression".to_owned(),
-            ErrorKind::ExpectedField => "Expected field name".to_owned(),
-            ErrorKind::UndelimitedStr => "Undelimited string".to_owned(),
-            ErrorKind::Missing(str) => format!("Missing '{}'", str),
+            ErrorKind::SyntaxError => write!(f, "Syntax error"),
+            ErrorKind::Unexpected => write!(f, "Unexpected token"),
+            ErrorKind::ExpectedExpr => write!(f, "Expected expression"),
+            ErrorKind::ExpectedField => write!(f, "Expected field name"),
+            ErrorKind::UndelimitedStr => write!(f, "Undelimited string"),
+            ErrorKind::Missing(str) => write!(f, "Missing '{}'", str),
             ErrorKind::Redeclaration(str) => {
-                format!("Redeclaring existing identifier '{}'", str)
+                write!(f, "Redeclaring existing identifier '{}'", str)
+            }
+            ErrorKind::Undeclared(str, suggestions) => {
+                write!(f, "Undeclared identifier '{}'", str)?;
+
+                if !suggestions.is_empty() {
+                    let names: Vec<_> = suggestions.iter().map(|s| format!("'{}'", s)).collect();
+                    write!(f, ", did you mean {}?", names.join(" or "))?;
+                }
+
+                Ok(())
+            }
+            ErrorKind::ContinueOutside => write!(f, "continue outside of a loop"),
+            ErrorKind::BreakOutside => write!(f, "break outside of a loop"),
+            ErrorKind::ReturnOutside => write!(f, "return outside of a function"),
+            ErrorKind::InvalidName => write!(f, "Invalid identifier name"),
+            ErrorKind::ModuleNotFound(path) => write!(f, "Cannot find module '{}'", path),
+            ErrorKind::NotExported(name, path) => {
+                write!(f, "'{}' is not exported by module '{}'", name, path)
             }
-            ErrorKind::Undeclared(str) => format!("Undeclared identifier '{}'", str),
-            ErrorKind::ContinueOutside => "continue outside of a loop".to_owned(),
-            ErrorKind::BreakOutside => "break outside of a loop".to_owned(),
-            ErrorKind::ReturnOutside => "return outside of a function".to_owned(),
-            ErrorKind::InvalidName => "Invalid identifier name".to_owned(),
+        }
+    }
+}
+
+impl ErrorKind {
+    fn data(&self) -> Option<DiagnosticData> {
+        match self {
+            ErrorKind::Undeclared(name, suggestions) => Some(DiagnosticData::Undeclared {
+                name: name.clone(),
+                suggestions: suggestions.clone(),
+            }),
+            _ => None,
         }
     }
 }
@@ -43,6 +75,7 @@ pub fn error(kind: ErrorKind, range: Range) -> Diagnostic {
         severity: Some(DiagnosticSeverity::ERROR),
         source: Some("icelang_ls".to_owned()),
         message: kind.to_string(),
+        data: kind.data().map(DiagnosticData::into_value),
         ..Default::default()
     }
 }
@@ -52,10 +85,10 @@ pub enum WarnKind {
     UnusedResult,
 }
 
-impl ToString for WarnKind {
-    fn to_string(&self) -> String {
+impl fmt::Display for WarnKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            WarnKind::UnusedResult => "Unused result".to_owned(),
+            WarnKind::UnusedResult => write!(f, "Unused result"),
         }
     }
 }
@@ -78,13 +111,23 @@ pub enum HintKind {
     Assign,
 }
 
-impl ToString for HintKind {
-    fn to_string(&self) -> String {
+impl fmt::Display for HintKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HintKind::Unreachable => write!(f, "Unreachable code"),
+            HintKind::EmptyMatch => write!(f, "Empty match expression"),
+            HintKind::Unused(str) => write!(f, "'{}' is never used", str),
+            HintKind::Assign => write!(f, "Consider assigning the resulting value"),
+        }
+    }
+}
+
+impl HintKind {
+    fn data(&self) -> Option<DiagnosticData> {
         match self {
-            HintKind::Unreachable => "Unreachable code".to_string(),
-            HintKind::EmptyMatch => "Empty match expression".to_string(),
-            HintKind::Unused(str) => format!("'{}' is never used", str),
-            HintKind::Assign => "Consider assigning the resulting value".to_owned(),
+            HintKind::Assign => Some(DiagnosticData::Assign),
+            HintKind::Unreachable => Some(DiagnosticData::Unreachable),
+            _ => None,
         }
     }
 }
@@ -102,6 +145,53 @@ pub fn hint(kind: HintKind, range: Range) -> Diagnostic {
         source: Some("icelang_ls".to_owned()),
         message: kind.to_string(),
         tags: Some(tags),
+        data: kind.data().map(DiagnosticData::into_value),
         ..Default::default()
     }
 }
+
+pub fn lint(
+    lint: Lint,
+    severity: DiagnosticSeverity,
+    range: Range,
+    replacement: String,
+) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(severity),
+        code: Some(NumberOrString::String(lint.name().to_owned())),
+        source: Some("icelang_ls".to_owned()),
+        message: lint.to_string(),
+        data: Some(DiagnosticData::Simplify { lint, replacement }.into_value()),
+        ..Default::default()
+    }
+}
+
+/// Extra informations attached to a diagnostic, used by code actions to
+/// provide fixes without having to parse the message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DiagnosticData {
+    Undeclared {
+        name: String,
+        suggestions: Vec<String>,
+    },
+    Assign,
+    Unreachable,
+    Simplify {
+        lint: Lint,
+        replacement: String,
+    },
+}
+
+impl DiagnosticData {
+    pub fn from_diagnostic(diagnostic: &Diagnostic) -> Option<Self> {
+        let data = diagnostic.data.clone()?;
+
+        serde_json::from_value(data).ok()
+    }
+
+    fn into_value(self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
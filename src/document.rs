@@ -1,41 +1,472 @@
-use tower_lsp::lsp_types::{DidChangeTextDocumentParams, DidOpenTextDocumentParams};
-use tree_sitter::{Parser, Tree};
+use std::sync::Arc;
 
-use crate::declarations::DeclarationMap;
+use ropey::Rope;
+use tower_lsp::lsp_types::{
+    ClientCapabilities, Diagnostic, DidChangeTextDocumentParams, DidOpenTextDocumentParams,
+    Position, PositionEncodingKind, Range, TextEdit, Url,
+};
+use tree_sitter::{InputEdit, Parser, Point, Tree};
 
+use crate::{
+    analyzer::analyze,
+    declarations::DeclarationMap,
+    module::{check_imports, get_exports, get_imports, Exports, Import},
+    settings::Settings,
+};
+
+/// Unit of the character offsets used by the client, positions used by the
+/// server are always in bytes like tree-sitter points.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+    Utf8,
+    #[default]
+    Utf16,
+}
+
+impl PositionEncoding {
+    /// Prefers UTF-8 when the client supports it, UTF-16 is mandatory.
+    pub fn negotiate(capabilities: &ClientCapabilities) -> Self {
+        let encodings = capabilities
+            .general
+            .as_ref()
+            .and_then(|general| general.position_encodings.as_ref());
+
+        match encodings {
+            Some(encodings) if encodings.contains(&PositionEncodingKind::UTF8) => Self::Utf8,
+            _ => Self::Utf16,
+        }
+    }
+
+    pub fn kind(&self) -> PositionEncodingKind {
+        match self {
+            Self::Utf8 => PositionEncodingKind::UTF8,
+            Self::Utf16 => PositionEncodingKind::UTF16,
+        }
+    }
+
+    /// Returns the length of the text in the unit of the encoding.
+    pub fn text_len(&self, text: &str) -> usize {
+        match self {
+            Self::Utf8 => text.len(),
+            Self::Utf16 => text.encode_utf16().count(),
+        }
+    }
+}
+
+/// Text of a document at a given version, never modified once created so
+/// that it can be shared with the requests and the analysis.
 pub struct Document {
+    pub version: i32,
     pub content: String,
+    pub rope: Rope,
+    pub encoding: PositionEncoding,
     pub tree: Tree,
-    pub parser: Parser,
-    pub declarations: DeclarationMap, // FIXME: use symbol table
 }
 
 impl Document {
-    pub fn from_params(params: DidOpenTextDocumentParams) -> Option<Self> {
-        let content = params.text_document.text;
-        let mut parser = Parser::new();
-
-        parser
-            .set_language(tree_sitter_icelang::language())
-            .expect("Error loading icelang grammar");
-
-        let tree = parser.parse(&content, None)?;
-        let declarations = DeclarationMap::new();
+    pub fn new(version: i32, content: String, encoding: PositionEncoding) -> Option<Self> {
+        let rope = Rope::from_str(&content);
+        let tree = new_parser().parse(&content, None)?;
 
         Some(Self {
+            version,
             content,
+            rope,
+            encoding,
             tree,
-            parser,
-            declarations,
         })
     }
 
-    pub fn did_change(&mut self, params: DidChangeTextDocumentParams) {
-        let changes = &params.content_changes[0];
-        let text = changes.text.clone();
+    pub fn from_params(
+        params: DidOpenTextDocumentParams,
+        encoding: PositionEncoding,
+    ) -> Option<Self> {
+        let document = params.text_document;
+
+        Self::new(document.version, document.text, encoding)
+    }
+
+    /// Returns the next version of the document, the rope and the tree are
+    /// cheap to clone and the old tree is reused when parsing.
+    pub fn did_change(&self, params: DidChangeTextDocumentParams) -> Self {
+        let mut document = Self {
+            version: params.text_document.version,
+            content: String::new(),
+            rope: self.rope.clone(),
+            encoding: self.encoding,
+            tree: self.tree.clone(),
+        };
+        let mut incremental = true;
+
+        for change in params.content_changes {
+            match change.range {
+                Some(range) => document.edit(range, &change.text),
+                None => {
+                    document.rope = Rope::from_str(&change.text);
+                    incremental = false;
+                }
+            }
+        }
+
+        let rope = &document.rope;
+        let old_tree = incremental.then_some(&document.tree);
+        let tree = new_parser().parse_with(
+            &mut |byte, _| {
+                if byte >= rope.len_bytes() {
+                    return &[] as &[u8];
+                }
+
+                let (chunk, chunk_start, _, _) = rope.chunk_at_byte(byte);
+
+                &chunk.as_bytes()[byte - chunk_start..]
+            },
+            old_tree,
+        );
+
+        if let Some(tree) = tree {
+            document.tree = tree;
+        }
+
+        document.content = document.rope.to_string();
+        document
+    }
+
+    /// Applies a change to the rope and reports it to the tree so that the
+    /// next parse can reuse the unchanged nodes.
+    fn edit(&mut self, range: Range, text: &str) {
+        let start_char = self.position_to_char(range.start);
+        let end_char = self.position_to_char(range.end).max(start_char);
+        let start_byte = self.rope.char_to_byte(start_char);
+        let old_end_byte = self.rope.char_to_byte(end_char);
+        let start_position = self.byte_to_point(start_byte);
+        let old_end_position = self.byte_to_point(old_end_byte);
+
+        self.rope.remove(start_char..end_char);
+        self.rope.insert(start_char, text);
+
+        let new_end_byte = start_byte + text.len();
+        let new_end_position = self.byte_to_point(new_end_byte);
+
+        self.tree.edit(&InputEdit {
+            start_byte,
+            old_end_byte,
+            new_end_byte,
+            start_position,
+            old_end_position,
+            new_end_position,
+        });
+    }
+
+    /// Converts a client position to a position with a byte column.
+    pub fn decode_position(&self, position: Position) -> Position {
+        let char = self.position_to_char(position);
+        let point = self.byte_to_point(self.rope.char_to_byte(char));
+
+        Position::new(point.row as u32, point.column as u32)
+    }
+
+    /// Converts a position with a byte column to a client position.
+    pub fn encode_position(&self, position: Position) -> Position {
+        let line = position.line as usize;
+
+        if line >= self.rope.len_lines() {
+            return self.encode_position(self.decode_position(position));
+        }
+
+        let line_start = self.rope.line_to_char(line);
+        let line_end = line_start + self.line_len(line);
+        let byte = self.rope.line_to_byte(line) + position.character as usize;
+        let char = self
+            .rope
+            .byte_to_char(byte.min(self.rope.len_bytes()))
+            .min(line_end);
+        let column = match self.encoding {
+            PositionEncoding::Utf8 => {
+                self.rope.char_to_byte(char) - self.rope.char_to_byte(line_start)
+            }
+            PositionEncoding::Utf16 => {
+                self.rope.char_to_utf16_cu(char) - self.rope.char_to_utf16_cu(line_start)
+            }
+        };
+
+        Position::new(line as u32, column as u32)
+    }
+
+    pub fn decode_range(&self, range: Range) -> Range {
+        Range::new(
+            self.decode_position(range.start),
+            self.decode_position(range.end),
+        )
+    }
+
+    pub fn encode_range(&self, range: Range) -> Range {
+        Range::new(
+            self.encode_position(range.start),
+            self.encode_position(range.end),
+        )
+    }
+
+    pub fn encode_edits(&self, edits: Vec<TextEdit>) -> Vec<TextEdit> {
+        edits
+            .into_iter()
+            .map(|edit| TextEdit::new(self.encode_range(edit.range), edit.new_text))
+            .collect()
+    }
+
+    pub fn encode_diagnostics(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| Diagnostic {
+                range: self.encode_range(diagnostic.range),
+                ..diagnostic
+            })
+            .collect()
+    }
+
+    fn position_to_char(&self, position: Position) -> usize {
+        let line = position.line as usize;
+
+        if line >= self.rope.len_lines() {
+            return self.rope.len_chars();
+        }
+
+        let line_start = self.rope.line_to_char(line);
+        let line_end = line_start + self.line_len(line);
+        let column = position.character as usize;
+        let char = match self.encoding {
+            PositionEncoding::Utf8 => {
+                let byte = self.rope.char_to_byte(line_start) + column;
+
+                self.rope.byte_to_char(byte.min(self.rope.len_bytes()))
+            }
+            PositionEncoding::Utf16 => {
+                let utf16 = self.rope.char_to_utf16_cu(line_start) + column;
+
+                self.rope
+                    .utf16_cu_to_char(utf16.min(self.rope.len_utf16_cu()))
+            }
+        };
+
+        char.min(line_end)
+    }
+
+    /// Returns the number of chars of a line, without the line ending.
+    fn line_len(&self, line: usize) -> usize {
+        let slice = self.rope.line(line);
+        let mut len = slice.len_chars();
+
+        while len > 0 && matches!(slice.char(len - 1), '\n' | '\r') {
+            len -= 1;
+        }
+
+        len
+    }
+
+    fn byte_to_point(&self, byte: usize) -> Point {
+        let row = self.rope.byte_to_line(byte);
+        let column = byte - self.rope.line_to_byte(row);
+
+        Point::new(row, column)
+    }
+}
+
+/// Immutable result of the analysis of a document version, requests are
+/// served from the latest snapshot without locking the document.
+pub struct Snapshot {
+    pub document: Arc<Document>,
+    pub declarations: DeclarationMap, // FIXME: use symbol table
+    pub diagnostics: Vec<Diagnostic>,
+    pub imports: Vec<Import>,
+    pub exports: Exports,
+}
+
+impl Snapshot {
+    /// Analyzes the document, `exports` gives the exports of the imported
+    /// modules and returns `None` for unknown modules.
+    pub fn analyze(
+        document: Arc<Document>,
+        uri: &Url,
+        settings: &Settings,
+        exports: &dyn Fn(&Url) -> Option<Exports>,
+    ) -> Self {
+        let source = document.content.as_bytes();
+        let mut result = analyze(source, &document.tree, &settings.lints);
+        let imports = get_imports(source, &document.tree, uri, &settings.module_paths);
+
+        result.diagnostics.extend(check_imports(
+            source,
+            &document.tree,
+            &result.declarations,
+            &imports,
+            exports,
+        ));
+
+        Self {
+            exports: get_exports(source, &document.tree),
+            declarations: result.declarations,
+            diagnostics: result.diagnostics,
+            imports,
+            document,
+        }
+    }
+}
+
+fn new_parser() -> Parser {
+    let mut parser = Parser::new();
+
+    parser
+        .set_language(tree_sitter_icelang::language())
+        .expect("Error loading icelang grammar");
+
+    parser
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{TextDocumentContentChangeEvent, VersionedTextDocumentIdentifier};
+
+    use super::*;
+
+    const SOURCE: &str = "set a = 'é€😀'\r\nprint(a)\nfunction f() {\n}\n";
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+    }
+
+    fn apply(document: &Document, changes: &[(Range, &str)]) -> Document {
+        let uri = Url::parse("file:///test.ic").unwrap();
+        let content_changes = changes
+            .iter()
+            .map(|(range, text)| TextDocumentContentChangeEvent {
+                range: Some(*range),
+                range_length: None,
+                text: text.to_string(),
+            })
+            .collect();
+
+        document.did_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri, document.version + 1),
+            content_changes,
+        })
+    }
+
+    fn assert_parsed(document: &Document) {
+        let fresh = Document::new(0, document.content.clone(), document.encoding).unwrap();
+
+        assert_eq!(document.rope.to_string(), document.content);
+        assert_eq!(
+            document.tree.root_node().to_sexp(),
+            fresh.tree.root_node().to_sexp()
+        );
+    }
+
+    #[test]
+    fn incremental_changes() {
+        let changes = [
+            (range((0, 4), (0, 5)), "b"),
+            (range((0, 14), (0, 14)), " + 1"),
+            (range((1, 6), (2, 0)), "b)\n"),
+            (range((3, 0), (3, 0)), "    return\n"),
+            (range((0, 0), (1, 0)), ""),
+            (range((4, 0), (4, 0)), "f('😀')"),
+        ];
+        let expected = [
+            "set b = 'é€😀'\r\nprint(a)\nfunction f() {\n}\n",
+            "set b = 'é€😀' + 1\r\nprint(a)\nfunction f() {\n}\n",
+            "set b = 'é€😀' + 1\r\nprint(b)\nfunction f() {\n}\n",
+            "set b = 'é€😀' + 1\r\nprint(b)\nfunction f() {\n    return\n}\n",
+            "print(b)\nfunction f() {\n    return\n}\n",
+            "print(b)\nfunction f() {\n    return\n}\nf('😀')",
+        ];
+        let mut document = Document::new(0, SOURCE.to_owned(), PositionEncoding::Utf16).unwrap();
+
+        for (change, expected) in changes.into_iter().zip(expected) {
+            document = apply(&document, &[change]);
+
+            assert_eq!(document.content, expected);
+            assert_parsed(&document);
+        }
+
+        // the changes of a notification apply one after the other
+        let changes = [
+            (range((0, 0), (0, 0)), "-- 😀\r\n"),
+            (range((5, 3), (5, 5)), "é"),
+        ];
+        let document = apply(&document, &changes);
+
+        assert_eq!(
+            document.content,
+            "-- 😀\r\nprint(b)\nfunction f() {\n    return\n}\nf('é')"
+        );
+        assert_parsed(&document);
+
+        let document = Document::new(0, SOURCE.to_owned(), PositionEncoding::Utf8).unwrap();
+        let document = apply(&document, &[(range((0, 11), (0, 18)), "a")]);
+
+        assert_eq!(
+            document.content,
+            "set a = 'éa'\r\nprint(a)\nfunction f() {\n}\n"
+        );
+        assert_parsed(&document);
+    }
+
+    #[test]
+    fn position_round_trip() {
+        let utf8 = Document::new(0, SOURCE.to_owned(), PositionEncoding::Utf8).unwrap();
+        let utf16 = Document::new(0, SOURCE.to_owned(), PositionEncoding::Utf16).unwrap();
+        // UTF-16 and byte columns around the multibyte and astral characters
+        let columns = [(0, 0), (9, 9), (10, 11), (11, 14), (13, 18), (14, 19)];
+
+        for (column, byte) in columns {
+            let position = Position::new(0, column);
+            let decoded = Position::new(0, byte);
+
+            assert_eq!(utf16.decode_position(position), decoded);
+            assert_eq!(utf16.encode_position(decoded), position);
+            assert_eq!(utf8.decode_position(decoded), decoded);
+            assert_eq!(utf8.encode_position(decoded), decoded);
+        }
+
+        let position = Position::new(1, 8);
+
+        assert_eq!(utf16.decode_position(position), position);
+        assert_eq!(utf16.encode_position(position), position);
+    }
+
+    #[test]
+    fn position_out_of_bounds() {
+        let utf8 = Document::new(0, SOURCE.to_owned(), PositionEncoding::Utf8).unwrap();
+        let utf16 = Document::new(0, SOURCE.to_owned(), PositionEncoding::Utf16).unwrap();
+        // inside a character, past the end of a line and past the end of file
+        let cases = [
+            ((0, 12), (0, 14)),
+            ((0, 100), (0, 19)),
+            ((1, 50), (1, 8)),
+            ((9, 3), (4, 0)),
+        ];
+
+        for (position, decoded) in cases {
+            let position = Position::new(position.0, position.1);
+            let decoded = Position::new(decoded.0, decoded.1);
+
+            assert_eq!(utf16.decode_position(position), decoded);
+        }
 
-        // FIXME: edit old tree
-        self.tree = self.parser.parse(&text, None).unwrap();
-        self.content = text;
+        assert_eq!(
+            utf8.decode_position(Position::new(0, 15)),
+            Position::new(0, 14)
+        );
+        assert_eq!(
+            utf8.decode_position(Position::new(0, 100)),
+            Position::new(0, 19)
+        );
+        assert_eq!(
+            utf16.encode_range(range((0, 100), (9, 3))),
+            range((0, 14), (4, 0))
+        );
+        assert_eq!(
+            utf8.encode_range(range((0, 15), (1, 50))),
+            range((0, 14), (1, 8))
+        );
     }
 }
@@ -0,0 +1,1100 @@
+use std::cmp::Reverse;
+
+use serde::Deserialize;
+use tower_lsp::lsp_types::{FormattingOptions, Position, Range, TextEdit};
+use tree_sitter::{Node, Parser, Point, Tree};
+
+use crate::{
+    document::Document,
+    utils::{point_to_position, position_to_point},
+};
+
+use Part::{Field, Text};
+
+const STATEMENT_CONTAINER: [&str; 2] = ["program", "stmt_block"];
+
+const LIST_NODE: [&str; 6] = [
+    "program",
+    "stmt_block",
+    "match_body",
+    "expr_object",
+    "expr_array",
+    "args",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteStyle {
+    #[default]
+    Preserve,
+    Double,
+    Single,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrailingComma {
+    #[default]
+    Multiline,
+    Never,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormatOptions {
+    pub indent_width: usize,
+    pub use_tabs: bool,
+    pub max_width: usize,
+    pub quote_style: QuoteStyle,
+    pub trailing_comma: TrailingComma,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent_width: 4,
+            use_tabs: false,
+            max_width: 80,
+            quote_style: QuoteStyle::default(),
+            trailing_comma: TrailingComma::default(),
+        }
+    }
+}
+
+impl From<&FormattingOptions> for FormatOptions {
+    fn from(value: &FormattingOptions) -> Self {
+        Self {
+            indent_width: value.tab_size as usize,
+            use_tabs: !value.insert_spaces,
+            ..Default::default()
+        }
+    }
+}
+
+pub fn format_document(document: &Document, options: &FormatOptions) -> Option<Vec<TextEdit>> {
+    let content = &document.content;
+    let formatted = format(content, &document.tree, options)?;
+
+    if &formatted == content {
+        return Some(vec![]);
+    }
+
+    let range = Range::new(Position::new(0, 0), get_end_position(content));
+
+    Some(vec![TextEdit::new(range, formatted)])
+}
+
+/// Formats the statements overlapping the range, works on files containing
+/// syntax errors as long as the statements themselves are valid.
+pub fn format_range(
+    document: &Document,
+    range: Range,
+    options: &FormatOptions,
+) -> Option<Vec<TextEdit>> {
+    let start = position_to_point(range.start);
+    let end = position_to_point(range.end);
+    let root = document.tree.root_node();
+    let mut container = root.descendant_for_point_range(start, end)?;
+
+    while !STATEMENT_CONTAINER.contains(&container.kind()) {
+        container = container.parent()?;
+    }
+
+    let mut cursor = container.walk();
+    let items: Vec<_> = container
+        .named_children(&mut cursor)
+        .filter(|item| item.end_position() >= start && item.start_position() <= end)
+        .skip_while(|item| {
+            item.prev_named_sibling()
+                .is_some_and(|previous| is_trailing_comment(item, &previous))
+        })
+        .collect();
+
+    format_items(document, &items, options)
+}
+
+/// Formats the statement affected by the typed character: the statement
+/// closed by `}` or containing `,`, or the one ended by `;` or a newline.
+/// The rest of the current block is re-indented, which also applies to the
+/// statements being typed that can't be formatted yet.
+pub fn format_on_type(
+    document: &Document,
+    position: Position,
+    character: &str,
+    options: &FormatOptions,
+) -> Option<Vec<TextEdit>> {
+    let root = document.tree.root_node();
+    let mut statements = get_statements(&root).into_iter();
+    let anchor = match character {
+        "\n" => Point::new((position.line as usize).checked_sub(1)?, usize::MAX),
+        _ => Point::new(
+            position.line as usize,
+            (position.character as usize).checked_sub(1)?,
+        ),
+    };
+    let statement = match character {
+        "}" | "," => {
+            statements.rfind(|node| node.start_position() <= anchor && node.end_position() > anchor)
+        }
+        ";" | "\n" => statements
+            .filter(|node| node.end_position() <= anchor && node.end_position().row == anchor.row)
+            .max_by_key(|node| (node.end_byte(), Reverse(node.start_byte()))),
+        _ => return None,
+    };
+    let block = match statement {
+        Some(statement) => statement.parent()?,
+        None => get_container(&root, anchor),
+    };
+    let formatted = statement.and_then(|statement| {
+        let edits = format_items(document, &[statement], options)?;
+
+        Some((statement, edits))
+    });
+    let lines: Vec<_> = document.content.split('\n').collect();
+    let mut cursor = block.walk();
+    let mut edits = Vec::new();
+
+    for item in block.named_children(&mut cursor) {
+        match &formatted {
+            Some((statement, formatted)) if *statement == item => {
+                edits.extend_from_slice(formatted)
+            }
+            _ => edits.extend(reindent(&lines, &item, get_level(&item), options)),
+        }
+    }
+
+    if let Some(close) = block.child(block.child_count().saturating_sub(1)) {
+        if block.kind() == "stmt_block" && close.kind() == "}" {
+            edits.extend(reindent(&lines, &close, get_level(&block), options));
+        }
+    }
+
+    Some(edits)
+}
+
+/// Parses and formats a whole file.
+pub fn format_source(source: &str, options: &FormatOptions) -> Option<String> {
+    let tree = parse(source)?;
+
+    format(source, &tree, options)
+}
+
+/// Formats a whole file, returns `None` when the file contains syntax errors
+/// or when the formatted output would not be equivalent to the source.
+pub fn format(source: &str, tree: &Tree, options: &FormatOptions) -> Option<String> {
+    let root = tree.root_node();
+
+    if root.has_error() {
+        return None;
+    }
+
+    let formatter = Formatter::new(source, options);
+    let mut result = formatter.format_statements(&root, 0);
+
+    if !result.is_empty() {
+        result.push('\n');
+    }
+
+    match is_equivalent(source, &root, &result) {
+        true => Some(result),
+        false => None,
+    }
+}
+
+/// Piece of the layout of a node: a literal text or one of its fields.
+enum Part<'p> {
+    Text(&'p str),
+    Field(&'p str),
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    options: &'a FormatOptions,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(source: &'a str, options: &'a FormatOptions) -> Self {
+        Self { source, options }
+    }
+
+    /// Formats a node starting at the given column of its line.
+    fn format_node(&self, node: &Node, level: usize, column: usize) -> String {
+        if has_comment(node) && !LIST_NODE.contains(&node.kind()) {
+            return self.format_tokens(node, level, column);
+        }
+
+        let format = |parts: &[Part]| self.format_parts(node, parts, level, column);
+
+        match node.kind() {
+            "comment" => self.text(node).trim_end().to_owned(),
+            "stmt_block" => self.format_block(node, level),
+            "stmt_var_decl" => format(&[Text("set "), Field("name"), Text(" = "), Field("value")]),
+            "stmt_func_decl" => format(&[
+                Text("function "),
+                Field("name"),
+                Field("args"),
+                Text(" "),
+                Field("body"),
+            ]),
+            "stmt_loop" => format(&[Text("loop "), Field("body")]),
+            "stmt_while" => format(&[Text("while "), Field("condition"), Text(" "), Field("body")]),
+            "stmt_for" => format(&[
+                Text("for "),
+                Field("iterator"),
+                Text(" in "),
+                Field("iterable"),
+                Text(" "),
+                Field("body"),
+            ]),
+            "iterator" => self.join(node, ", ", level, column),
+            "stmt_continue" => "continue".to_owned(),
+            "stmt_break" => "break".to_owned(),
+            "stmt_return" => format(&[Text("return "), Field("value")]),
+            "stmt_expression" | "literal_pattern" => self.join(node, "", level, column),
+            "expr_literal" => self.join(node, "", level, column),
+            "string" => self.format_string(node),
+            "expr_group" => format!("({})", self.join(node, "", level, column + 1)),
+            "expr_array" => self.format_list(node, ("[", "]"), level, column, false),
+            "expr_object" => {
+                let expand = node
+                    .named_child(0)
+                    .is_some_and(|child| child.start_position().row > node.start_position().row);
+
+                self.format_list(node, ("{ ", " }"), level, column, expand)
+            }
+            "args" => self.format_list(node, ("(", ")"), level, column, false),
+            "prop" => format(&[Field("name"), Text(": "), Field("value")]),
+            "expr_unary" => {
+                let operator = node.child(0).unwrap().kind();
+                let operand = self.join(node, "", level, column + operator.len());
+
+                match operator == "-" && operand.starts_with('-') {
+                    true => format!("{} {}", operator, operand),
+                    false => format!("{}{}", operator, operand),
+                }
+            }
+            "expr_binary" => format(&[
+                Field("lhs"),
+                Text(" "),
+                Field("operator"),
+                Text(" "),
+                Field("rhs"),
+            ]),
+            "expr_index" => format(&[Field("arg"), Text("["), Field("index"), Text("]")]),
+            "expr_field" => format(&[Field("arg"), Text("."), Field("field")]),
+            "expr_call" => format(&[Field("func"), Field("args")]),
+            "expr_lambda" => format(&[Text("lambda"), Field("args"), Text(" "), Field("body")]),
+            "expr_if" => {
+                let mut result =
+                    format(&[Text("if "), Field("condition"), Text(" "), Field("body")]);
+                let mut cursor = node.walk();
+                let else_branch = node
+                    .children_by_field_name("else_branch", &mut cursor)
+                    .find(|child| child.is_named());
+
+                if let Some(else_branch) = else_branch {
+                    result.push_str(" else ");
+
+                    let column = self.end_column(&result, column);
+                    result.push_str(&self.format_node(&else_branch, level, column));
+                }
+
+                result
+            }
+            "expr_match" => format(&[Text("match "), Field("value"), Text(" "), Field("body")]),
+            "match_body" => self.format_list(node, ("{", "}"), level, column, true),
+            "match_arm" => format(&[Field("pattern"), Text(": "), Field("value")]),
+            "or_pattern" => self.join(node, ", ", level, column),
+            "default_arm" => format(&[Text("_: "), Field("value")]),
+            _ => self.text(node).trim_end().to_owned(),
+        }
+    }
+
+    /// Writes the parts one after the other, each field being formatted at
+    /// the column reached by the previous parts.
+    fn format_parts(&self, node: &Node, parts: &[Part], level: usize, column: usize) -> String {
+        let mut result = String::new();
+
+        for part in parts {
+            match part {
+                Text(text) => result.push_str(text),
+                Field(name) => {
+                    let field = node.child_by_field_name(name).unwrap();
+                    let column = self.end_column(&result, column);
+
+                    result.push_str(&self.format_node(&field, level, column));
+                }
+            }
+        }
+
+        result
+    }
+
+    fn format_string(&self, node: &Node) -> String {
+        let text = self.text(node);
+        let content = &text[1..text.len() - 1];
+        let quote = match self.options.quote_style {
+            QuoteStyle::Preserve => return text.to_owned(),
+            QuoteStyle::Double => '"',
+            QuoteStyle::Single => '\'',
+        };
+
+        // strings have no escape sequences, the quotes can't always be changed
+        match content.contains(quote) {
+            true => text.to_owned(),
+            false => format!("{}{}{}", quote, content, quote),
+        }
+    }
+
+    fn format_block(&self, node: &Node, level: usize) -> String {
+        if node.named_child_count() == 0 {
+            return "{}".to_owned();
+        }
+
+        format!(
+            "{{\n{}\n{}}}",
+            self.format_statements(node, level + 1),
+            self.indent(level)
+        )
+    }
+
+    fn format_statements(&self, node: &Node, level: usize) -> String {
+        let mut cursor = node.walk();
+        let items: Vec<_> = node.named_children(&mut cursor).collect();
+
+        self.format_items(&items, level)
+    }
+
+    /// Formats consecutive statements of a block or of the program, one per line.
+    fn format_items(&self, items: &[Node], level: usize) -> String {
+        let top_level = items
+            .first()
+            .and_then(|item| item.parent())
+            .is_some_and(|parent| parent.kind() == "program");
+        let column = self.width(&self.indent(level));
+        let mut result = String::new();
+        let mut previous: Option<Node> = None;
+
+        for (index, item) in items.iter().enumerate() {
+            if let Some(previous) = previous {
+                if is_trailing_comment(item, &previous) {
+                    result.push(' ');
+                    result.push_str(&self.format_node(item, level, column));
+                    continue;
+                }
+
+                let blank = item.start_position().row > previous.end_position().row + 1;
+                let documented = previous.kind() == "comment"
+                    && item.start_position().row == previous.end_position().row + 1;
+                let declaration = top_level
+                    && (previous.kind() == "stmt_func_decl"
+                        || !documented && leads_to_function(&items[index..]));
+
+                result.push('\n');
+
+                if blank || declaration {
+                    result.push('\n');
+                }
+            }
+
+            result.push_str(&self.indent(level));
+            result.push_str(&self.format_node(item, level, column));
+
+            if item.next_sibling().is_some_and(|next| next.kind() == ";") {
+                result.push(';');
+            }
+
+            previous = Some(*item);
+        }
+
+        result
+    }
+
+    /// Formats a comma separated list on a single line when it fits, or with
+    /// an item per line and trailing commas otherwise. Block lambdas don't
+    /// prevent the single line, their body is laid out around it.
+    fn format_list(
+        &self,
+        node: &Node,
+        delimiters: (&str, &str),
+        level: usize,
+        column: usize,
+        expand: bool,
+    ) -> String {
+        let (open, close) = delimiters;
+        let mut cursor = node.walk();
+        let items: Vec<_> = node.named_children(&mut cursor).collect();
+
+        if items.is_empty() {
+            return format!("{}{}", open.trim(), close.trim());
+        }
+
+        let single_line = !expand && !items.iter().any(|item| item.kind() == "comment");
+        let item_column = self.width(&self.indent(level + 1));
+        let mut formatted: Vec<_> = items
+            .iter()
+            .map(|item| match single_line && is_block_lambda(item) {
+                true => self.format_node(item, level, item_column),
+                false => self.format_node(item, level + 1, item_column),
+            })
+            .collect();
+
+        if single_line
+            && items
+                .iter()
+                .zip(&formatted)
+                .all(|(item, text)| !text.contains('\n') || is_block_lambda(item))
+        {
+            let line = format!("{}{}{}", open, formatted.join(", "), close);
+            let first = line.lines().next().unwrap_or_default();
+            let last = line.lines().last().unwrap_or_default();
+
+            if column + self.width(first) <= self.options.max_width
+                && self.width(last) <= self.options.max_width
+            {
+                return line;
+            }
+        }
+
+        if single_line {
+            for (item, text) in items.iter().zip(&mut formatted) {
+                if is_block_lambda(item) {
+                    *text = self.format_node(item, level + 1, item_column);
+                }
+            }
+        }
+
+        let mut result = open.trim().to_owned();
+        let mut previous: Option<&Node> = None;
+        let last = items.iter().rposition(|item| item.kind() != "comment");
+
+        for (index, (item, text)) in items.iter().zip(formatted).enumerate() {
+            if let Some(previous) = previous {
+                if is_trailing_comment(item, previous) {
+                    result.push(' ');
+                    result.push_str(&text);
+                    continue;
+                }
+
+                if item.start_position().row > previous.end_position().row + 1 {
+                    result.push('\n');
+                }
+            }
+
+            result.push('\n');
+            result.push_str(&self.indent(level + 1));
+            result.push_str(&text);
+
+            let trailing = Some(index) == last;
+
+            if item.kind() != "comment"
+                && (!trailing || self.options.trailing_comma == TrailingComma::Multiline)
+            {
+                result.push(',');
+            }
+
+            previous = Some(item);
+        }
+
+        result.push('\n');
+        result.push_str(&self.indent(level));
+        result.push_str(close.trim());
+
+        result
+    }
+
+    /// Formats the children of a node one after the other, used for the nodes
+    /// containing comments which always end their line. The lines following
+    /// a comment are continuation lines, indented one level deeper.
+    fn format_tokens(&self, node: &Node, level: usize, column: usize) -> String {
+        let mut cursor = node.walk();
+        let mut result = String::new();
+        let mut previous: Option<Node> = None;
+
+        for child in node.children(&mut cursor) {
+            if let Some(previous) = previous {
+                if previous.kind() == "comment" {
+                    let indent = match child.kind() {
+                        "else" | "stmt_block" => level,
+                        _ => level + 1,
+                    };
+
+                    result.push('\n');
+                    result.push_str(&self.indent(indent));
+                } else if is_spaced(&previous, &child) {
+                    result.push(' ');
+                }
+            }
+
+            match child.is_named() {
+                true => {
+                    let column = self.end_column(&result, column);
+                    result.push_str(&self.format_node(&child, level, column));
+                }
+                false => result.push_str(self.text(&child)),
+            }
+
+            previous = Some(child);
+        }
+
+        result
+    }
+
+    fn join(&self, node: &Node, separator: &str, level: usize, column: usize) -> String {
+        let mut cursor = node.walk();
+        let mut result = String::new();
+
+        for (index, child) in node.named_children(&mut cursor).enumerate() {
+            if index > 0 {
+                result.push_str(separator);
+            }
+
+            let column = self.end_column(&result, column);
+            result.push_str(&self.format_node(&child, level, column));
+        }
+
+        result
+    }
+
+    /// Returns the column reached once the text is written from the column.
+    fn end_column(&self, text: &str, column: usize) -> usize {
+        match text.rsplit_once('\n') {
+            Some((_, line)) => self.width(line),
+            None => column + self.width(text),
+        }
+    }
+
+    /// Returns the width of a text, a tab counting as an indentation level.
+    fn width(&self, text: &str) -> usize {
+        text.chars()
+            .map(|char| match char {
+                '\t' => self.options.indent_width,
+                _ => 1,
+            })
+            .sum()
+    }
+
+    fn indent(&self, level: usize) -> String {
+        match self.options.use_tabs {
+            true => "\t".repeat(level),
+            false => " ".repeat(level * self.options.indent_width),
+        }
+    }
+
+    /// Returns the whitespace of the given width, using as many tabs as
+    /// possible when the indentation uses tabs.
+    fn whitespace(&self, width: usize) -> String {
+        match self.options.use_tabs {
+            true => format!(
+                "{}{}",
+                "\t".repeat(width / self.options.indent_width.max(1)),
+                " ".repeat(width % self.options.indent_width.max(1))
+            ),
+            false => " ".repeat(width),
+        }
+    }
+
+    fn text(&self, node: &Node) -> &'a str {
+        &self.source[node.byte_range()]
+    }
+}
+
+/// Replaces the given consecutive statements by their formatted version,
+/// including the indentation of the first line and a trailing semicolon.
+fn format_items(
+    document: &Document,
+    items: &[Node],
+    options: &FormatOptions,
+) -> Option<Vec<TextEdit>> {
+    let (first, last) = (items.first()?, items.last()?);
+
+    if items.iter().any(|item| item.has_error()) {
+        return None;
+    }
+
+    let source = &document.content;
+    let formatter = Formatter::new(source, options);
+    let formatted = formatter.format_items(items, get_level(first));
+    let line_start = source[..first.start_byte()]
+        .rfind('\n')
+        .map_or(0, |index| index + 1);
+    let (start, formatted) = match source[line_start..first.start_byte()].trim().is_empty() {
+        true => (line_start, formatted.as_str()),
+        false => (first.start_byte(), formatted.trim_start()),
+    };
+    let end = match last.next_sibling() {
+        Some(next) if next.kind() == ";" => next,
+        _ => *last,
+    };
+    let original = &source[start..end.end_byte()];
+
+    if original == formatted {
+        return Some(vec![]);
+    }
+
+    let tree = parse(original)?;
+
+    if !is_equivalent(original, &tree.root_node(), formatted) {
+        return None;
+    }
+
+    let start = Point::new(first.start_position().row, start - line_start);
+    let range = Range::new(
+        point_to_position(start),
+        point_to_position(end.end_position()),
+    );
+
+    Some(vec![TextEdit::new(range, formatted.to_owned())])
+}
+
+/// Returns the indentation level of a statement or a list item.
+/// Returns the innermost block or program containing the point.
+fn get_container<'a>(root: &Node<'a>, point: Point) -> Node<'a> {
+    let mut current = root.descendant_for_point_range(point, point);
+
+    while let Some(node) = current {
+        if STATEMENT_CONTAINER.contains(&node.kind()) {
+            return node;
+        }
+
+        current = node.parent();
+    }
+
+    *root
+}
+
+/// Moves a node starting its line to the indentation of the level, the
+/// following lines of the node keep their indentation relative to the first
+/// one, except the lines continuing a string.
+fn reindent(lines: &[&str], node: &Node, level: usize, options: &FormatOptions) -> Vec<TextEdit> {
+    let formatter = Formatter::new("", options);
+    let start = node.start_position();
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let Some(first) = lines.get(start.row) else {
+        return vec![];
+    };
+
+    if indent(first) != start.column {
+        return vec![];
+    }
+
+    let width = formatter.width(&formatter.indent(level));
+    let offset = width as isize - formatter.width(&first[..start.column]) as isize;
+
+    if offset == 0 {
+        return vec![];
+    }
+
+    let mut edits = Vec::new();
+
+    for row in start.row..=node.end_position().row {
+        let Some(line) = lines.get(row) else {
+            break;
+        };
+        let point = Point::new(row, 0);
+
+        if line.trim().is_empty() || row > start.row && is_in_string(node, point) {
+            continue;
+        }
+
+        let end = indent(line);
+        let width = formatter.width(&line[..end]) as isize + offset;
+        let range = Range::new(
+            point_to_position(point),
+            point_to_position(Point::new(row, end)),
+        );
+
+        edits.push(TextEdit::new(
+            range,
+            formatter.whitespace(width.max(0) as usize),
+        ));
+    }
+
+    edits
+}
+
+fn is_in_string(node: &Node, point: Point) -> bool {
+    let mut current = node.descendant_for_point_range(point, point);
+
+    while let Some(node) = current {
+        if node.kind() == "string" {
+            return node.start_position() < point;
+        }
+
+        current = node.parent();
+    }
+
+    false
+}
+
+fn get_level(node: &Node) -> usize {
+    let mut level = 0;
+    let mut current = node.parent();
+
+    while let Some(node) = current {
+        if LIST_NODE.contains(&node.kind()) && node.kind() != "program" {
+            level += 1;
+        }
+
+        current = node.parent();
+    }
+
+    level
+}
+
+/// Returns all the statements of the tree, outer statements first.
+fn get_statements<'a>(root: &Node<'a>) -> Vec<Node<'a>> {
+    let mut statements: Vec<Node> = Vec::new();
+    let mut stack = vec![*root];
+
+    while let Some(node) = stack.pop() {
+        let mut cursor = node.walk();
+        let children: Vec<_> = node.named_children(&mut cursor).collect();
+
+        if STATEMENT_CONTAINER.contains(&node.kind()) {
+            statements.extend(children.iter().filter(|child| child.kind() != "comment"));
+        }
+
+        stack.extend(children.into_iter().rev());
+    }
+
+    statements.sort_by_key(|node| (node.start_byte(), Reverse(node.end_byte())));
+    statements
+}
+
+fn is_block_lambda(node: &Node) -> bool {
+    node.kind() == "expr_lambda"
+        && node
+            .child_by_field_name("body")
+            .is_some_and(|body| body.kind() == "stmt_block")
+}
+
+fn has_comment(node: &Node) -> bool {
+    let mut cursor = node.walk();
+    let result = node
+        .named_children(&mut cursor)
+        .any(|child| child.kind() == "comment");
+
+    result
+}
+
+/// Checks whether two consecutive children of a node are separated by a
+/// space, following the layout of `Formatter::format_node`.
+fn is_spaced(previous: &Node, next: &Node) -> bool {
+    // the operator of a unary expression is followed by its operand
+    if previous
+        .parent()
+        .is_some_and(|parent| parent.kind() == "expr_unary")
+    {
+        return previous.is_named();
+    }
+
+    !matches!(
+        next.kind(),
+        "args" | "." | "[" | "]" | ")" | "," | ":" | ";"
+    ) && !matches!(previous.kind(), "." | "[" | "(")
+}
+
+fn is_trailing_comment(node: &Node, previous: &Node) -> bool {
+    node.kind() == "comment" && node.start_position().row == previous.end_position().row
+}
+
+/// Checks whether the items start with a function declaration, possibly
+/// preceded by the comments documenting it.
+fn leads_to_function(items: &[Node]) -> bool {
+    let mut index = 0;
+
+    while items[index].kind() == "comment" {
+        match items.get(index + 1) {
+            Some(next) if next.start_position().row == items[index].end_position().row + 1 => {
+                index += 1
+            }
+            _ => return false,
+        }
+    }
+
+    items[index].kind() == "stmt_func_decl"
+}
+
+/// Parses the formatted output and compares it to the source tree, comments
+/// are compared separately since they are allowed to move between nodes.
+fn is_equivalent(source: &str, root: &Node, formatted: &str) -> bool {
+    let Some(tree) = parse(formatted) else {
+        return false;
+    };
+
+    let strip = |sexp: String| sexp.replace(" (comment)", "").replace("(comment) ", "");
+    let comments = |source: &str, root: &Node| {
+        let mut comments = Vec::new();
+        let mut stack = vec![*root];
+
+        while let Some(node) = stack.pop() {
+            let mut cursor = node.walk();
+
+            if node.kind() == "comment" {
+                comments.push(source[node.byte_range()].trim_end().to_owned());
+            }
+
+            stack.extend(node.named_children(&mut cursor));
+        }
+
+        comments.sort();
+        comments
+    };
+
+    strip(root.to_sexp()) == strip(tree.root_node().to_sexp())
+        && comments(source, root) == comments(formatted, &tree.root_node())
+}
+
+fn parse(source: &str) -> Option<Tree> {
+    let mut parser = Parser::new();
+
+    parser
+        .set_language(tree_sitter_icelang::language())
+        .expect("Error loading icelang grammar");
+
+    parser.parse(source, None)
+}
+
+fn get_end_position(content: &str) -> Position {
+    let line = content.matches('\n').count();
+    let column = content.len() - content.rfind('\n').map_or(0, |index| index + 1);
+
+    Position::new(line as u32, column as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::PositionEncoding;
+
+    fn format(source: &str) -> Option<String> {
+        format_source(source, &FormatOptions::default())
+    }
+
+    fn apply(source: &str, edits: Option<Vec<TextEdit>>) -> Option<String> {
+        let mut edits = edits?;
+        let offset = |position: Position| {
+            let line: usize = source
+                .split_inclusive('\n')
+                .take(position.line as usize)
+                .map(str::len)
+                .sum();
+
+            line + position.character as usize
+        };
+        let mut result = source.to_owned();
+
+        edits.sort_by_key(|edit| Reverse(offset(edit.range.start)));
+
+        for edit in edits {
+            result.replace_range(
+                offset(edit.range.start)..offset(edit.range.end),
+                &edit.new_text,
+            );
+        }
+
+        Some(result)
+    }
+
+    fn format_typed(source: &str, line: u32, character: &str) -> Option<String> {
+        let document = Document::new(0, source.to_owned(), PositionEncoding::Utf8).unwrap();
+        let column = source.split('\n').nth(line as usize).unwrap().len() as u32;
+        let position = match character {
+            "\n" => Position::new(line + 1, 0),
+            _ => Position::new(line, column),
+        };
+        let edits = format_on_type(&document, position, character, &FormatOptions::default());
+
+        apply(source, edits)
+    }
+
+    #[test]
+    fn format_program() {
+        let source = concat!(
+            "set a=[1,2 ,3]\n",
+            "function f(x){return x*2}\n",
+            "if a {print( f(1) )} else if b {  } else {\n",
+            "  match a { 1, 2: 3,_: 4 }\n",
+            "}\n",
+        );
+        let expected = concat!(
+            "set a = [1, 2, 3]\n",
+            "\n",
+            "function f(x) {\n",
+            "    return x * 2\n",
+            "}\n",
+            "\n",
+            "if a {\n",
+            "    print(f(1))\n",
+            "} else if b {} else {\n",
+            "    match a {\n",
+            "        1, 2: 3,\n",
+            "        _: 4,\n",
+            "    }\n",
+            "}\n",
+        );
+
+        assert_eq!(format(source).as_deref(), Some(expected));
+    }
+
+    #[test]
+    fn format_idempotent() {
+        let sources = [
+            "set a = { x: 1,\n  y: [1, 2] }\nfor i in a { print(i) }\n",
+            "-- doc\nfunction f() {\n-- inside\nloop { break }\n}\nprint(f()) -- trailing\n",
+            "if a { 1 } -- c\n  else { 2 }\n",
+            "set b = a and -- c\n  c\n",
+            "print([\n  1, -- one\n  2,\n])\n",
+            "function f(x) -- c\n{ return -x.y[0] }\n",
+            "for i in a -- c\n{ print(i) }\n",
+            "set a = (1 + -- c\n2)\n",
+            "f(lambda() { return 1 }, [lambda(x) { x }])\n",
+        ];
+
+        for source in sources {
+            let formatted = format(source).unwrap();
+
+            assert_eq!(format(&formatted).as_deref(), Some(formatted.as_str()));
+        }
+    }
+
+    #[test]
+    fn format_comments() {
+        let source = "if a { 1 } -- c\n  else { 2 }\n";
+        let expected = "if a {\n    1\n} -- c\nelse {\n    2\n}\n";
+
+        assert_eq!(format(source).as_deref(), Some(expected));
+
+        let source = "while true {\n  set b = a and -- c\n      c\n}\n";
+        let expected = "while true {\n    set b = a and -- c\n        c\n}\n";
+
+        assert_eq!(format(source).as_deref(), Some(expected));
+
+        let source = "set a = (1 + -- c\n2)\n";
+        let expected = "set a = (1 + -- c\n    2)\n";
+
+        assert_eq!(format(source).as_deref(), Some(expected));
+
+        let source = "set a = [\n  1, -- one\n  -- two\n  2\n]\n";
+        let expected = "set a = [\n    1, -- one\n    -- two\n    2,\n]\n";
+
+        assert_eq!(format(source).as_deref(), Some(expected));
+    }
+
+    #[test]
+    fn format_wrapping() {
+        let source = format!("result.append({})\n", ["arg"; 15].join(", "));
+        let expected = format!("result.append(\n{})\n", "    arg,\n".repeat(15));
+
+        assert_eq!(source.trim_end().len(), 88);
+        assert_eq!(format(&source), Some(expected));
+
+        let source = "set aVeryLongVariableName = [1, 2, 3, 4]\n";
+        let options = FormatOptions {
+            max_width: 30,
+            ..Default::default()
+        };
+        let expected = concat!(
+            "set aVeryLongVariableName = [\n",
+            "    1,\n",
+            "    2,\n",
+            "    3,\n",
+            "    4,\n",
+            "]\n",
+        );
+
+        assert_eq!(format(source).as_deref(), Some(source));
+        assert_eq!(format_source(source, &options).as_deref(), Some(expected));
+
+        let source = "items.map(lambda(x) { print(x) return x * 2 }, 1)\n";
+        let expected = concat!(
+            "items.map(lambda(x) {\n",
+            "    print(x)\n",
+            "    return x * 2\n",
+            "}, 1)\n",
+        );
+
+        assert_eq!(format(source).as_deref(), Some(expected));
+    }
+
+    #[test]
+    fn format_on_range() {
+        let source = "set a=1\nif a {\nset b=2\n}\nset c=(\n";
+        let document = Document::new(0, source.to_owned(), PositionEncoding::Utf8).unwrap();
+        let format = |range| {
+            let edits = format_range(&document, range, &FormatOptions::default());
+
+            apply(source, edits)
+        };
+        let range = Range::new(Position::new(0, 2), Position::new(0, 2));
+        let expected = "set a = 1\nif a {\nset b=2\n}\nset c=(\n";
+
+        assert_eq!(format(range).as_deref(), Some(expected));
+
+        let range = Range::new(Position::new(2, 0), Position::new(3, 1));
+        let expected = "set a=1\nif a {\n    set b = 2\n}\nset c=(\n";
+
+        assert_eq!(format(range).as_deref(), Some(expected));
+
+        let range = Range::new(Position::new(4, 0), Position::new(4, 7));
+
+        assert_eq!(format(range), None);
+    }
+
+    #[test]
+    fn format_typed_character() {
+        let source = "function f() {\nreturn 1}\n";
+        let expected = "function f() {\n    return 1\n}\n";
+
+        assert_eq!(format_typed(source, 1, "}").as_deref(), Some(expected));
+
+        let source = "if a {\n      set b=1\n  print( b )\n\n  }\n";
+        let expected = "if a {\n    set b=1\n    print(b)\n\n}\n";
+
+        assert_eq!(format_typed(source, 2, "\n").as_deref(), Some(expected));
+
+        let source = "set a = [1,2]; set b=2;\n";
+        let expected = "set a = [1,2]; set b = 2;\n";
+
+        assert_eq!(format_typed(source, 0, ";").as_deref(), Some(expected));
+    }
+
+    #[test]
+    fn format_typed_incomplete() {
+        let source = "function f() {\n  print(1)\n      set a = [1, 2,\n}\n";
+        let expected = "function f() {\n    print(1)\n    set a = [1, 2,\n}\n";
+
+        assert_eq!(format_typed(source, 2, ",").as_deref(), Some(expected));
+
+        let source = "  set a = [\n      1,\n";
+        let expected = "set a = [\n    1,\n";
+
+        assert_eq!(format_typed(source, 1, "\n").as_deref(), Some(expected));
+    }
+
+    #[test]
+    fn format_fallback() {
+        let source = "set a = 1 -- one\nset b = 2\n";
+        let tree = parse(source).unwrap();
+        let root = tree.root_node();
+
+        assert!(is_equivalent(
+            source,
+            &root,
+            "set a = 1\n-- one\nset b = 2\n"
+        ));
+        assert!(!is_equivalent(source, &root, "set a = 1\nset b = 2\n"));
+        assert!(!is_equivalent(
+            source,
+            &root,
+            "set a = 1 -- one\nset b = 3 * 2\n"
+        ));
+        assert!(!is_equivalent(
+            source,
+            &root,
+            "set a = 1 -- one\nset b = (\n"
+        ));
+        assert_eq!(format("set a = (1\n"), None);
+    }
+}
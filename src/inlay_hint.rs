@@ -0,0 +1,153 @@
+use tower_lsp::lsp_types::{InlayHint, InlayHintKind, InlayHintLabel, Range};
+use tree_sitter::Node;
+
+use crate::{
+    analyzer::Identifier,
+    ast::NodeType,
+    declarations::DeclarationKind,
+    document::Snapshot,
+    settings::InlayHintSettings,
+    utils::{get_node_range, point_to_position},
+};
+
+/// Returns the hints of the calls overlapping the range, the positions are
+/// left in the document encoding to the caller.
+pub fn get_inlay_hints(
+    snapshot: &Snapshot,
+    range: Range,
+    settings: &InlayHintSettings,
+) -> Vec<InlayHint> {
+    let mut hints = Vec::new();
+
+    if settings.parameter_names {
+        let root = snapshot.document.tree.root_node();
+
+        get_parameter_hints(snapshot, &root, range, &mut hints);
+    }
+
+    hints
+}
+
+/// Shows the parameter names before the arguments of the user functions,
+/// unless the argument is a variable of the same name.
+fn get_parameter_hints(snapshot: &Snapshot, node: &Node, range: Range, hints: &mut Vec<InlayHint>) {
+    let node_range = get_node_range(node);
+
+    if node_range.end < range.start || node_range.start > range.end {
+        return;
+    }
+
+    if let Some(args) = get_call_parameters(snapshot, node) {
+        let arguments = node.child_by_field_name("args").unwrap();
+        let mut cursor = arguments.walk();
+        let arguments = arguments
+            .named_children(&mut cursor)
+            .filter(|argument| argument.kind() != "comment");
+
+        for (argument, name) in arguments.zip(args) {
+            let source = snapshot.document.content.as_bytes();
+
+            if argument.utf8_text(source) == Ok(name.as_str()) {
+                continue;
+            }
+
+            hints.push(InlayHint {
+                position: point_to_position(argument.start_position()),
+                label: InlayHintLabel::String(format!("{}:", name)),
+                kind: Some(InlayHintKind::PARAMETER),
+                text_edits: None,
+                tooltip: None,
+                padding_left: None,
+                padding_right: Some(true),
+                data: None,
+            });
+        }
+    }
+
+    let mut cursor = node.walk();
+
+    for child in node.named_children(&mut cursor) {
+        get_parameter_hints(snapshot, &child, range, hints);
+    }
+}
+
+fn get_call_parameters(snapshot: &Snapshot, node: &Node) -> Option<Vec<String>> {
+    if NodeType::from(node) != NodeType::ExprCall {
+        return None;
+    }
+
+    let function = node.child_by_field_name("func")?;
+
+    if NodeType::from(&function) != NodeType::ExprIdentifier {
+        return None;
+    }
+
+    let identifier = Identifier {
+        name: function
+            .utf8_text(snapshot.document.content.as_bytes())
+            .ok()?
+            .to_owned(),
+        range: get_node_range(&function),
+    };
+
+    let declaration = snapshot.declarations.get(&identifier)?;
+
+    match &declaration.kind {
+        DeclarationKind::Function(args) if !declaration.is_builtin() => Some(args.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tower_lsp::lsp_types::{Position, Url};
+
+    use super::*;
+    use crate::{
+        document::{Document, PositionEncoding},
+        settings::Settings,
+    };
+
+    fn get_labels(source: &str) -> Vec<(Position, String)> {
+        let uri = Url::parse("file:///test.ic").unwrap();
+        let document = Document::new(0, source.to_owned(), PositionEncoding::Utf8).unwrap();
+        let snapshot = Snapshot::analyze(Arc::new(document), &uri, &Settings::default(), &|_| None);
+        let range = Range::new(Position::new(0, 0), Position::new(u32::MAX, 0));
+        let settings = InlayHintSettings {
+            parameter_names: true,
+        };
+
+        get_inlay_hints(&snapshot, range, &settings)
+            .into_iter()
+            .map(|hint| match hint.label {
+                InlayHintLabel::String(label) => (hint.position, label),
+                InlayHintLabel::LabelParts(_) => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parameter_hints() {
+        let source = "function f(a, b) {}\nset b = 1\nf(1, b)\nprint(1)\nf(f(2))\n";
+        let expected = [
+            (Position::new(2, 2), "a:".to_owned()),
+            (Position::new(4, 2), "a:".to_owned()),
+            (Position::new(4, 4), "a:".to_owned()),
+        ];
+
+        assert_eq!(get_labels(source), expected);
+    }
+
+    #[test]
+    fn parameter_hints_comments() {
+        let source = "function f(a, b) {}\nf(-- c\n 1, 2)\n";
+        let expected = [
+            (Position::new(2, 1), "a:".to_owned()),
+            (Position::new(2, 4), "b:".to_owned()),
+        ];
+
+        assert_eq!(get_labels(source), expected);
+    }
+}
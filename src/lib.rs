@@ -2,7 +2,17 @@ pub mod analyzer;
 pub mod ast;
 pub mod backend;
 pub mod builtins;
+pub mod code_action;
+pub mod config;
 pub mod declarations;
 pub mod diagnostic;
 pub mod document;
+pub mod formatter;
+pub mod inlay_hint;
+pub mod lint;
+pub mod module;
+pub mod move_item;
+pub mod progress;
+pub mod settings;
 pub mod utils;
+pub mod workspace;
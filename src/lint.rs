@@ -0,0 +1,260 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::DiagnosticSeverity;
+use tree_sitter::Node;
+
+use crate::ast::{has_side_effects, needs_parentheses, NodeType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lint {
+    CompoundAssignment,
+    NegatedComparison,
+    RedundantIf,
+    BoolComparison,
+}
+
+impl Lint {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::CompoundAssignment => "compound_assignment",
+            Lint::NegatedComparison => "negated_comparison",
+            Lint::RedundantIf => "redundant_if",
+            Lint::BoolComparison => "bool_comparison",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Lint::CompoundAssignment => "Use compound assignment",
+            Lint::NegatedComparison => "Invert comparison",
+            Lint::RedundantIf => "Replace if expression with its condition",
+            Lint::BoolComparison => "Remove comparison with boolean literal",
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::CompoundAssignment => write!(f, "Assignment can use a compound operator"),
+            Lint::NegatedComparison => write!(f, "Negated comparison can be inverted"),
+            Lint::RedundantIf => write!(f, "Redundant if expression returning booleans"),
+            Lint::BoolComparison => write!(f, "Redundant comparison with a boolean literal"),
+        }
+    }
+}
+
+/// Severity of a lint, `true` enables it as a hint and `false` turns it off.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "LintSetting")]
+pub enum LintLevel {
+    #[default]
+    Off,
+    Hint,
+    Info,
+    Warning,
+    Error,
+}
+
+impl LintLevel {
+    pub fn severity(&self) -> Option<DiagnosticSeverity> {
+        match self {
+            LintLevel::Off => None,
+            LintLevel::Hint => Some(DiagnosticSeverity::HINT),
+            LintLevel::Info => Some(DiagnosticSeverity::INFORMATION),
+            LintLevel::Warning => Some(DiagnosticSeverity::WARNING),
+            LintLevel::Error => Some(DiagnosticSeverity::ERROR),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LintSetting {
+    Enabled(bool),
+    Level(String),
+}
+
+impl TryFrom<LintSetting> for LintLevel {
+    type Error = String;
+
+    fn try_from(value: LintSetting) -> Result<Self, String> {
+        match value {
+            LintSetting::Enabled(true) => Ok(LintLevel::Hint),
+            LintSetting::Enabled(false) => Ok(LintLevel::Off),
+            LintSetting::Level(level) => match level.as_str() {
+                "off" => Ok(LintLevel::Off),
+                "hint" => Ok(LintLevel::Hint),
+                "info" => Ok(LintLevel::Info),
+                "warning" => Ok(LintLevel::Warning),
+                "error" => Ok(LintLevel::Error),
+                _ => Err(format!(
+                    "unknown lint level '{}', expected 'off', 'hint', 'info', 'warning' or 'error'",
+                    level
+                )),
+            },
+        }
+    }
+}
+
+/// Optional lints, all of them are disabled by default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintConfig {
+    pub compound_assignment: LintLevel,
+    pub negated_comparison: LintLevel,
+    pub redundant_if: LintLevel,
+    pub bool_comparison: LintLevel,
+}
+
+impl LintConfig {
+    pub fn get_level(&self, lint: Lint) -> LintLevel {
+        match lint {
+            Lint::CompoundAssignment => self.compound_assignment,
+            Lint::NegatedComparison => self.negated_comparison,
+            Lint::RedundantIf => self.redundant_if,
+            Lint::BoolComparison => self.bool_comparison,
+        }
+    }
+}
+
+/// Returns the lint matching the node along with the simplified text.
+pub fn get_simplification(node: &Node, source: &[u8]) -> Option<(Lint, String)> {
+    let text = |node: &Node| node.utf8_text(source).unwrap();
+
+    match NodeType::from(node) {
+        NodeType::ExprBinary => {
+            let lhs = node.child_by_field_name("lhs")?;
+            let operator = node.child_by_field_name("operator")?.kind();
+            let rhs = node.child_by_field_name("rhs")?;
+
+            match operator {
+                "=" => {
+                    let operator = rhs.child_by_field_name("operator")?.kind();
+                    let operand = rhs.child_by_field_name("lhs")?;
+                    let value = rhs.child_by_field_name("rhs")?;
+
+                    if NodeType::from(&rhs) != NodeType::ExprBinary
+                        || !["+", "-", "*", "/", "%"].contains(&operator)
+                        || text(&operand) != text(&lhs)
+                        || has_side_effects(&lhs)
+                    {
+                        return None;
+                    }
+
+                    let simplified = format!("{} {}= {}", text(&lhs), operator, text(&value));
+
+                    Some((Lint::CompoundAssignment, simplified))
+                }
+                "==" | "!=" => {
+                    let (value, literal) = match (get_bool(&lhs, source), get_bool(&rhs, source)) {
+                        (None, Some(literal)) => (lhs, literal),
+                        (Some(literal), None) => (rhs, literal),
+                        _ => return None,
+                    };
+                    let simplified = match literal == (operator == "==") {
+                        true => text(&value).to_owned(),
+                        false => negate(&value, text(&value)),
+                    };
+
+                    Some((Lint::BoolComparison, simplified))
+                }
+                _ => None,
+            }
+        }
+        NodeType::ExprUnary => {
+            let operand = node.named_child(0)?;
+
+            if node.child(0)?.kind() != "!" || NodeType::from(&operand) != NodeType::ExprGroup {
+                return None;
+            }
+
+            let comparison = operand.named_child(0)?;
+            let operator = match comparison.child_by_field_name("operator")?.kind() {
+                "==" => "!=",
+                "!=" => "==",
+                _ => return None,
+            };
+            let lhs = comparison.child_by_field_name("lhs")?;
+            let rhs = comparison.child_by_field_name("rhs")?;
+            let simplified = format!("{} {} {}", text(&lhs), operator, text(&rhs));
+            let simplified = match needs_parentheses(&comparison, node) {
+                true => format!("({})", simplified),
+                false => simplified,
+            };
+
+            Some((Lint::NegatedComparison, simplified))
+        }
+        NodeType::ExprIf => {
+            let condition = node.child_by_field_name("condition")?;
+            let body = node.child_by_field_name("body")?;
+            let mut cursor = node.walk();
+            let else_branch = node
+                .children_by_field_name("else_branch", &mut cursor)
+                .find(|child| child.is_named())?;
+
+            if NodeType::from(&else_branch) != NodeType::StmtBlock {
+                return None;
+            }
+
+            let simplified = match (
+                get_block_bool(&body, source)?,
+                get_block_bool(&else_branch, source)?,
+            ) {
+                (true, false) => text(&condition).to_owned(),
+                (false, true) => negate(&condition, text(&condition)),
+                _ => return None,
+            };
+            let simplified = match needs_parentheses(&condition, node) {
+                true => format!("({})", simplified),
+                false => simplified,
+            };
+
+            Some((Lint::RedundantIf, simplified))
+        }
+        _ => None,
+    }
+}
+
+fn get_bool(node: &Node, source: &[u8]) -> Option<bool> {
+    if NodeType::from(node) != NodeType::ExprLiteral {
+        return None;
+    }
+
+    match node.named_child(0)?.utf8_text(source).unwrap() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn get_block_bool(node: &Node, source: &[u8]) -> Option<bool> {
+    let mut cursor = node.walk();
+    let statements: Vec<_> = node
+        .named_children(&mut cursor)
+        .filter(|child| child.kind() != "comment")
+        .collect();
+
+    match statements.as_slice() {
+        [statement] if NodeType::from(statement) == NodeType::StmtExpression => {
+            get_bool(&statement.named_child(0)?, source)
+        }
+        _ => None,
+    }
+}
+
+fn negate(node: &Node, text: &str) -> String {
+    match NodeType::from(node) {
+        NodeType::ExprLiteral
+        | NodeType::ExprIdentifier
+        | NodeType::ExprGroup
+        | NodeType::ExprCall
+        | NodeType::ExprField
+        | NodeType::ExprIndex
+        | NodeType::ExprArray
+        | NodeType::ExprObject => format!("!{}", text),
+        _ => format!("!({})", text),
+    }
+}
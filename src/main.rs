@@ -1,11 +1,118 @@
-use icelang_ls::backend::Backend;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use icelang_ls::{
+    backend::Backend, config::ProjectConfig, formatter::format_source, workspace::collect_files,
+};
 use tower_lsp::{LspService, Server};
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
+    let args: Vec<_> = env::args().skip(1).collect();
+
+    if args.first().is_some_and(|arg| arg == "fmt") {
+        return fmt(&args[1..]);
+    }
+
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
-    let (service, socket) = LspService::new(|client| Backend::new(client));
-    
+    let (service, socket) = LspService::build(Backend::new)
+        .custom_method("icelang/moveItem", Backend::move_item)
+        .custom_method("window/workDoneProgress/cancel", Backend::cancel_progress)
+        .finish();
+
     Server::new(stdin, stdout, socket).serve(service).await;
+
+    ExitCode::SUCCESS
+}
+
+/// `icelang-ls fmt [--check] [paths...]`, formats the files in place or lists
+/// the unformatted ones when checking.
+fn fmt(args: &[String]) -> ExitCode {
+    let check = args.iter().any(|arg| arg == "--check");
+    let mut paths: Vec<_> = args
+        .iter()
+        .filter(|arg| *arg != "--check")
+        .map(PathBuf::from)
+        .collect();
+
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+
+    let mut files = Vec::new();
+
+    for path in paths {
+        let mut collected = Vec::new();
+
+        collect_files(&path, &mut collected);
+
+        // the files given explicitly are formatted even when ignored
+        if path.is_dir() {
+            collected.retain(|file| !is_ignored(file));
+        }
+
+        files.append(&mut collected);
+    }
+
+    let mut success = true;
+
+    for file in files {
+        if let Err(err) = fmt_file(&file, check) {
+            eprintln!("{}: {}", file.display(), err);
+            success = false;
+        }
+    }
+
+    match success {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
+
+fn fmt_file(path: &Path, check: bool) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let options = match find_config(path)? {
+        Some((_, config)) => config.format.unwrap_or_default(),
+        None => Default::default(),
+    };
+    let formatted =
+        format_source(&source, &options).ok_or("cannot format a file containing syntax errors")?;
+
+    if formatted == source {
+        return Ok(());
+    }
+
+    match check {
+        true => Err("not formatted".to_owned()),
+        false => fs::write(path, formatted).map_err(|err| err.to_string()),
+    }
+}
+
+/// Reads the configuration of the project containing the file, along with
+/// the directory of the project file.
+fn find_config(path: &Path) -> Result<Option<(PathBuf, ProjectConfig)>, String> {
+    // the ancestors of a relative path stop at the current directory
+    let path = fs::canonicalize(path).map_err(|err| err.to_string())?;
+    let Some(config) = path.parent().and_then(ProjectConfig::find) else {
+        return Ok(None);
+    };
+    let directory = config.parent().unwrap_or(&config).to_owned();
+
+    Ok(Some((directory, ProjectConfig::load(&config)?)))
+}
+
+fn is_ignored(path: &Path) -> bool {
+    let (Ok(path), Ok(Some((directory, config)))) = (fs::canonicalize(path), find_config(path))
+    else {
+        return false;
+    };
+
+    config
+        .ignore
+        .iter()
+        .any(|ignored| path.starts_with(directory.join(ignored)))
 }
@@ -0,0 +1,394 @@
+use std::path::{Component, Path, PathBuf};
+
+use tower_lsp::lsp_types::{Diagnostic, Range, Url};
+use tree_sitter::{Node, Tree};
+
+use crate::{
+    analyzer::Identifier,
+    ast::{is_assignment, NodeType},
+    declarations::DeclarationMap,
+    diagnostic::{error, ErrorKind},
+    utils::get_node_range,
+    workspace::EXTENSION,
+};
+
+/// An `import('...')` call with a literal path.
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub path: String,
+    pub range: Range,
+    /// Name of the global variable the module is assigned to, in
+    /// `set name = import('...')`.
+    pub binding: Option<Range>,
+    /// Resolved module, kept even if it doesn't exist so that creating it
+    /// updates the importers.
+    pub uri: Option<Url>,
+}
+
+/// Fields of the value a module returns with `export(...)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Exports {
+    Fields(Vec<String>),
+    /// The exported value isn't an object, its fields aren't checked.
+    Unknown,
+}
+
+/// Resolves an import path relative to the directory of the importing file,
+/// then to the module paths, the extension can be omitted. Unknown modules
+/// resolve next to the importing file.
+pub fn resolve_module(from: &Url, path: &str, module_paths: &[PathBuf]) -> Option<Url> {
+    let directory = from.to_file_path().ok()?.parent()?.to_owned();
+    let candidate = |directory: &Path| {
+        let mut path = normalize(&directory.join(path));
+
+        if path.extension().is_none() {
+            path.set_extension(EXTENSION);
+        }
+
+        path
+    };
+    let local = candidate(&directory);
+    let path = match local.is_file() {
+        true => local,
+        false => module_paths
+            .iter()
+            .map(|directory| candidate(directory))
+            .find(|path| path.is_file())
+            .unwrap_or(local),
+    };
+
+    Url::from_file_path(path).ok()
+}
+
+pub fn get_imports(source: &[u8], tree: &Tree, uri: &Url, module_paths: &[PathBuf]) -> Vec<Import> {
+    let mut imports = Vec::new();
+
+    visit(tree.root_node(), &mut |node| {
+        let Some(path) = get_import_path(node, source) else {
+            return;
+        };
+        let binding = node
+            .parent()
+            .filter(|parent| NodeType::from(parent) == NodeType::StmtVarDecl)
+            .filter(|parent| parent.parent().is_some_and(|root| root.parent().is_none()))
+            .and_then(|parent| parent.child_by_field_name("name"))
+            .map(|name| get_node_range(&name));
+        let argument = node
+            .child_by_field_name("args")
+            .and_then(|args| args.named_child(0))
+            .unwrap_or(*node);
+
+        imports.push(Import {
+            uri: resolve_module(uri, &path, module_paths),
+            path,
+            range: get_node_range(&argument),
+            binding,
+        });
+    });
+
+    imports
+}
+
+/// Collects the fields of the exported objects, an object assigned to a
+/// global variable is followed along with the fields assigned later.
+pub fn get_exports(source: &[u8], tree: &Tree) -> Exports {
+    let mut values = Vec::new();
+
+    visit(tree.root_node(), &mut |node| {
+        if get_function_name(node, source) == Some("export") {
+            values.extend(
+                node.child_by_field_name("args")
+                    .and_then(|args| args.named_child(0)),
+            );
+        }
+    });
+
+    let mut fields = Vec::new();
+
+    for value in values {
+        match get_object_fields(&value, source, tree) {
+            Some(object) => fields.extend(object),
+            None => return Exports::Unknown,
+        }
+    }
+
+    fields.sort();
+    fields.dedup();
+
+    Exports::Fields(fields)
+}
+
+/// Reports the missing modules and the fields which aren't exported by the
+/// imported modules, `exports` returns `None` for unknown modules. The
+/// variables are resolved with the declarations to find the bindings.
+pub fn check_imports(
+    source: &[u8],
+    tree: &Tree,
+    declarations: &DeclarationMap,
+    imports: &[Import],
+    exports: &dyn Fn(&Url) -> Option<Exports>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let modules: Vec<_> = imports
+        .iter()
+        .map(|import| import.uri.as_ref().and_then(exports))
+        .collect();
+
+    for (import, module) in imports.iter().zip(&modules) {
+        if module.is_none() {
+            diagnostics.push(error(
+                ErrorKind::ModuleNotFound(import.path.clone()),
+                import.range,
+            ));
+        }
+    }
+
+    visit(tree.root_node(), &mut |node| {
+        if NodeType::from(node) != NodeType::ExprField {
+            return;
+        }
+
+        let (Some(arg), Some(field)) = (
+            node.child_by_field_name("arg"),
+            node.child_by_field_name("field"),
+        ) else {
+            return;
+        };
+        let position = match NodeType::from(&arg) {
+            NodeType::ExprIdentifier => {
+                let identifier = Identifier {
+                    name: arg.utf8_text(source).unwrap_or_default().to_owned(),
+                    range: get_node_range(&arg),
+                };
+                let decl = declarations.resolve(&identifier);
+
+                decl.and_then(|decl| {
+                    imports
+                        .iter()
+                        .position(|import| import.binding == Some(decl.name_range))
+                })
+            }
+            _ => {
+                let range = get_node_range(&arg);
+
+                get_import_path(&arg, source).and_then(|_| {
+                    imports.iter().position(|import| {
+                        range.start <= import.range.start && import.range.end <= range.end
+                    })
+                })
+            }
+        };
+        let Some(position) = position else {
+            return;
+        };
+        let Some(Exports::Fields(exported)) = &modules[position] else {
+            return;
+        };
+        let Ok(name) = field.utf8_text(source) else {
+            return;
+        };
+
+        if !exported.iter().any(|export| export == name) {
+            let kind = ErrorKind::NotExported(name.to_owned(), imports[position].path.clone());
+
+            diagnostics.push(error(kind, get_node_range(&field)));
+        }
+    });
+
+    diagnostics
+}
+
+/// Returns the path of `import('...')`, `None` for any other node.
+fn get_import_path(node: &Node, source: &[u8]) -> Option<String> {
+    if get_function_name(node, source) != Some("import") {
+        return None;
+    }
+
+    let literal = node.child_by_field_name("args")?.named_child(0)?;
+    let string = literal
+        .named_child(0)
+        .filter(|child| child.kind() == "string")?;
+    let text = string.utf8_text(source).ok()?;
+
+    // strip the quotes
+    text.get(1..text.len() - 1).map(str::to_owned)
+}
+
+/// Returns the name of the function called with a single argument.
+fn get_function_name<'a>(node: &Node, source: &'a [u8]) -> Option<&'a str> {
+    if NodeType::from(node) != NodeType::ExprCall {
+        return None;
+    }
+
+    let function = node.child_by_field_name("func")?;
+    let args = node.child_by_field_name("args")?;
+
+    match NodeType::from(&function) == NodeType::ExprIdentifier && args.named_child_count() == 1 {
+        true => function.utf8_text(source).ok(),
+        false => None,
+    }
+}
+
+fn get_object_fields(node: &Node, source: &[u8], tree: &Tree) -> Option<Vec<String>> {
+    match NodeType::from(node) {
+        NodeType::ExprObject => {
+            let mut cursor = node.walk();
+            let fields = node
+                .named_children(&mut cursor)
+                .filter_map(|prop| prop.child_by_field_name("name"))
+                .filter_map(|name| name.utf8_text(source).ok())
+                .map(|name| name.trim_matches(|c| c == '"' || c == '\'').to_owned())
+                .collect();
+
+            Some(fields)
+        }
+        NodeType::ExprIdentifier => {
+            let name = node.utf8_text(source).ok()?;
+            let root = tree.root_node();
+            let mut cursor = root.walk();
+            let value = root
+                .named_children(&mut cursor)
+                .filter(|child| NodeType::from(child) == NodeType::StmtVarDecl)
+                .filter(|decl| {
+                    decl.child_by_field_name("name")
+                        .is_some_and(|decl_name| decl_name.utf8_text(source) == Ok(name))
+                })
+                .last()?
+                .child_by_field_name("value")?;
+
+            if NodeType::from(&value) != NodeType::ExprObject {
+                return None;
+            }
+
+            let mut fields = get_object_fields(&value, source, tree)?;
+
+            // `name.field = value`
+            visit(root, &mut |node| {
+                let field = Some(*node)
+                    .filter(is_assignment)
+                    .and_then(|node| node.child_by_field_name("lhs"))
+                    .filter(|lhs| NodeType::from(lhs) == NodeType::ExprField)
+                    .filter(|lhs| {
+                        lhs.child_by_field_name("arg")
+                            .is_some_and(|arg| arg.utf8_text(source) == Ok(name))
+                    })
+                    .and_then(|lhs| lhs.child_by_field_name("field"))
+                    .and_then(|field| field.utf8_text(source).ok());
+
+                fields.extend(field.map(str::to_owned));
+            });
+
+            Some(fields)
+        }
+        _ => None,
+    }
+}
+
+fn visit<'tree>(node: Node<'tree>, callback: &mut impl FnMut(&Node<'tree>)) {
+    callback(&node);
+
+    let mut cursor = node.walk();
+
+    for child in node.named_children(&mut cursor) {
+        visit(child, callback);
+    }
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            component => result.push(component),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tower_lsp::lsp_types::{DiagnosticSeverity, Position};
+
+    use super::*;
+    use crate::{
+        document::{Document, PositionEncoding, Snapshot},
+        settings::Settings,
+    };
+
+    /// Returns the errors of a file importing `lib`, which exports `a`.
+    fn get_errors(source: &str) -> Vec<(Range, String)> {
+        let uri = Url::parse("file:///project/main.ic").unwrap();
+        let document = Document::new(0, source.to_owned(), PositionEncoding::Utf8).unwrap();
+        let exports = |uri: &Url| match uri.path() {
+            "/project/lib.ic" => Some(Exports::Fields(vec!["a".to_owned()])),
+            _ => None,
+        };
+        let snapshot = Snapshot::analyze(Arc::new(document), &uri, &Settings::default(), &exports);
+
+        snapshot
+            .diagnostics
+            .into_iter()
+            .filter(|diagnostic| diagnostic.severity == Some(DiagnosticSeverity::ERROR))
+            .map(|diagnostic| (diagnostic.range, diagnostic.message))
+            .collect()
+    }
+
+    fn range(line: u32, start: u32, end: u32) -> Range {
+        Range::new(Position::new(line, start), Position::new(line, end))
+    }
+
+    #[test]
+    fn module_not_found() {
+        let errors = get_errors("set m = import('lib')\nset n = import('missing')\n");
+
+        assert_eq!(
+            errors,
+            [(range(1, 15, 24), "Cannot find module 'missing'".to_owned())]
+        );
+    }
+
+    #[test]
+    fn not_exported() {
+        let errors = get_errors("set m = import('lib')\nprint(m.a, m.b, import('lib').c)\n");
+        let expected = [
+            (
+                range(1, 13, 14),
+                "'b' is not exported by module 'lib'".to_owned(),
+            ),
+            (
+                range(1, 30, 31),
+                "'c' is not exported by module 'lib'".to_owned(),
+            ),
+        ];
+
+        assert_eq!(errors, expected);
+    }
+
+    #[test]
+    fn shadowed_binding() {
+        let source = concat!(
+            "set m = import('lib')\n",
+            "function f(m) {\n",
+            "    return m.b\n",
+            "}\n",
+            "print(lambda(m) m.c)\n",
+            "print(m.d)\n",
+        );
+        let errors = get_errors(source);
+
+        assert_eq!(
+            errors,
+            [(
+                range(5, 8, 9),
+                "'d' is not exported by module 'lib'".to_owned()
+            )]
+        );
+    }
+}
@@ -0,0 +1,335 @@
+use serde::Deserialize;
+use tower_lsp::lsp_types::{Position, Range, TextDocumentIdentifier, TextEdit};
+use tree_sitter::Node;
+
+use crate::{
+    document::Document,
+    utils::{point_to_position, position_to_point},
+};
+
+const CONTAINER_NODE: [&str; 6] = [
+    "program",
+    "stmt_block",
+    "match_body",
+    "expr_object",
+    "expr_array",
+    "args",
+];
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveItemParams {
+    pub text_document: TextDocumentIdentifier,
+    pub position: Position,
+    pub direction: MoveDirection,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MoveDirection {
+    Up,
+    Down,
+}
+
+/// Swaps the innermost statement, match arm, property, element or argument
+/// under the cursor with its sibling, comments on the lines above an item
+/// are moved along with it.
+pub fn move_item(
+    document: &Document,
+    position: Position,
+    direction: MoveDirection,
+) -> Vec<TextEdit> {
+    let point = position_to_point(position);
+    let root = document.tree.root_node();
+    let mut current = root.descendant_for_point_range(point, point);
+
+    while let Some(node) = current {
+        if is_item(&node) {
+            if let Some(sibling) = get_sibling(&node, direction) {
+                return swap(document, &node, &sibling);
+            }
+        }
+
+        current = node.parent();
+    }
+
+    vec![]
+}
+
+fn is_item(node: &Node) -> bool {
+    node.is_named()
+        && node.kind() != "comment"
+        && node
+            .parent()
+            .is_some_and(|parent| CONTAINER_NODE.contains(&parent.kind()))
+}
+
+fn get_sibling<'a>(node: &Node<'a>, direction: MoveDirection) -> Option<Node<'a>> {
+    let mut sibling = match direction {
+        MoveDirection::Up => node.prev_named_sibling(),
+        MoveDirection::Down => node.next_named_sibling(),
+    };
+
+    while let Some(value) = sibling {
+        if value.kind() != "comment" {
+            return Some(value);
+        }
+
+        sibling = match direction {
+            MoveDirection::Up => value.prev_named_sibling(),
+            MoveDirection::Down => value.next_named_sibling(),
+        };
+    }
+
+    None
+}
+
+/// Swaps the items with the comments above them, the commas stay in place.
+/// Comments ending the line of an item follow it, unless both items share a
+/// line.
+fn swap(document: &Document, node: &Node, sibling: &Node) -> Vec<TextEdit> {
+    let content = &document.content;
+    let (first, second) = match node.start_byte() < sibling.start_byte() {
+        true => (*node, *sibling),
+        false => (*sibling, *node),
+    };
+    let first_range = get_item_range(&first);
+    let second_range = get_item_range(&second);
+    let mut edits = vec![
+        TextEdit::new(get_range(&first_range), get_text(content, &second_range)),
+        TextEdit::new(get_range(&second_range), get_text(content, &first_range)),
+    ];
+
+    if first.end_position().row == second.start_position().row {
+        return edits;
+    }
+
+    let first_comment = get_trailing_comment(&first);
+    let second_comment = get_trailing_comment(&second);
+
+    match (first_comment, second_comment) {
+        (Some(first_comment), Some(second_comment)) => {
+            let first_range = (first_comment, first_comment);
+            let second_range = (second_comment, second_comment);
+
+            edits.push(TextEdit::new(
+                get_range(&first_range),
+                get_text(content, &second_range),
+            ));
+            edits.push(TextEdit::new(
+                get_range(&second_range),
+                get_text(content, &first_range),
+            ));
+        }
+        (Some(comment), None) => edits.extend(move_comment(content, &comment, &first, &second)),
+        (None, Some(comment)) => edits.extend(move_comment(content, &comment, &second, &first)),
+        (None, None) => {}
+    }
+
+    edits
+}
+
+/// Moves the comment ending the line of an item to the end of the line of
+/// another item.
+fn move_comment(content: &str, comment: &Node, from: &Node, to: &Node) -> [TextEdit; 2] {
+    let removed = (get_line_end(from), *comment);
+    let line_end = point_to_position(get_line_end(to).end_position());
+    let text = format!(" {}", get_text(content, &(*comment, *comment)));
+
+    [
+        TextEdit::new(
+            Range::new(
+                point_to_position(removed.0.end_position()),
+                point_to_position(removed.1.end_position()),
+            ),
+            String::new(),
+        ),
+        TextEdit::new(Range::new(line_end, line_end), text),
+    ]
+}
+
+/// Returns the first and last nodes of an item, including the comments
+/// written on the lines right above it but not a comment ending the line of
+/// the previous item.
+fn get_item_range<'a>(node: &Node<'a>) -> (Node<'a>, Node<'a>) {
+    let mut start = *node;
+
+    while let Some(previous) = start.prev_named_sibling() {
+        let attached = previous.kind() == "comment"
+            && previous.end_position().row + 1 == start.start_position().row
+            && previous
+                .prev_named_sibling()
+                .is_none_or(|item| item.end_position().row != previous.start_position().row);
+
+        match attached {
+            true => start = previous,
+            false => break,
+        }
+    }
+
+    (start, *node)
+}
+
+/// Returns the comment written after an item and its comma on the same line.
+fn get_trailing_comment<'a>(node: &Node<'a>) -> Option<Node<'a>> {
+    let comment = get_line_end(node).next_sibling()?;
+
+    match comment.kind() == "comment" && comment.start_position().row == node.end_position().row {
+        true => Some(comment),
+        false => None,
+    }
+}
+
+/// Returns the comma following an item, or the item itself without comma.
+fn get_line_end<'a>(node: &Node<'a>) -> Node<'a> {
+    match node.next_sibling() {
+        Some(next) if next.kind() == "," => next,
+        _ => *node,
+    }
+}
+
+fn get_text(content: &str, (start, end): &(Node, Node)) -> String {
+    content[start.start_byte()..end.end_byte()].to_owned()
+}
+
+fn get_range((start, end): &(Node, Node)) -> Range {
+    Range::new(
+        point_to_position(start.start_position()),
+        point_to_position(end.end_position()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::PositionEncoding;
+
+    /// Moves the item at the cursor, marked by `|`, and applies the edits.
+    fn run(source: &str, direction: MoveDirection) -> String {
+        let offset = source.find('|').expect("missing cursor");
+        let content = source.replacen('|', "", 1);
+        let line = content[..offset].matches('\n').count();
+        let column = offset - content[..offset].rfind('\n').map_or(0, |index| index + 1);
+        let position = Position::new(line as u32, column as u32);
+        let document = Document::new(0, content.clone(), PositionEncoding::Utf8).unwrap();
+        let mut edits = move_item(&document, position, direction);
+        let offset = |position: Position| {
+            let line: usize = content
+                .split_inclusive('\n')
+                .take(position.line as usize)
+                .map(str::len)
+                .sum();
+
+            line + position.character as usize
+        };
+        let mut result = content.clone();
+
+        edits.sort_by_key(|edit| std::cmp::Reverse(offset(edit.range.start)));
+
+        for edit in edits {
+            result.replace_range(
+                offset(edit.range.start)..offset(edit.range.end),
+                &edit.new_text,
+            );
+        }
+
+        result
+    }
+
+    #[test]
+    fn move_list_items() {
+        use MoveDirection::{Down, Up};
+
+        let cases = [
+            ("set a = [|1, 2, 3]", Down, "set a = [2, 1, 3]"),
+            ("set a = [|1, 2, 3]", Up, "set a = [1, 2, 3]"),
+            ("set a = [1, 2, |3]", Up, "set a = [1, 3, 2]"),
+            ("set a = [1, 2, |3]", Down, "set a = [1, 2, 3]"),
+            ("f(|a, b)", Down, "f(b, a)"),
+            ("f(a, |b)", Up, "f(b, a)"),
+            ("f(a, |b)", Down, "f(a, b)"),
+            ("set a = { |x: 1, y: 2 }", Down, "set a = { y: 2, x: 1 }"),
+            (
+                "match a {\n    |1: 2,\n    _: 3,\n}",
+                Down,
+                "match a {\n    _: 3,\n    1: 2,\n}",
+            ),
+            (
+                "match a {\n    1: 2,\n    |_: 3,\n}",
+                Up,
+                "match a {\n    _: 3,\n    1: 2,\n}",
+            ),
+            ("match a {\n    |1: 2,\n}", Up, "match a {\n    1: 2,\n}"),
+        ];
+
+        for (source, direction, expected) in cases {
+            assert_eq!(run(source, direction), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn move_statements() {
+        use MoveDirection::{Down, Up};
+
+        let cases = [
+            ("set a = 1\n|print(a)\n", Up, "print(a)\nset a = 1\n"),
+            ("|set a = 1\nprint(a)\n", Up, "set a = 1\nprint(a)\n"),
+            (
+                "if a {\n    |b()\n    c()\n}\n",
+                Down,
+                "if a {\n    c()\n    b()\n}\n",
+            ),
+            (
+                "if a {\n    b(|x)\n    c()\n}\n",
+                Down,
+                "if a {\n    c()\n    b(x)\n}\n",
+            ),
+        ];
+
+        for (source, direction, expected) in cases {
+            assert_eq!(run(source, direction), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn move_comments() {
+        use MoveDirection::{Down, Up};
+
+        let cases = [
+            (
+                "set a = [\n    -- one\n    |1,\n    2,\n]",
+                Down,
+                "set a = [\n    2,\n    -- one\n    1,\n]",
+            ),
+            (
+                "set a = [\n    |1, -- one\n    2,\n]",
+                Down,
+                "set a = [\n    2,\n    1, -- one\n]",
+            ),
+            (
+                "set a = [\n    1, -- one\n    |2\n]",
+                Up,
+                "set a = [\n    2,\n    1 -- one\n]",
+            ),
+            (
+                "set a = [\n    1, -- one\n    |2, -- two\n]",
+                Up,
+                "set a = [\n    2, -- two\n    1, -- one\n]",
+            ),
+            (
+                "set a = [\n    1, |2, -- two\n]",
+                Up,
+                "set a = [\n    2, 1, -- two\n]",
+            ),
+            (
+                "set a = 1 -- one\n-- two\n|set b = 2\n",
+                Up,
+                "-- two\nset b = 2\nset a = 1 -- one\n",
+            ),
+        ];
+
+        for (source, direction, expected) in cases {
+            assert_eq!(run(source, direction), expected, "{}", source);
+        }
+    }
+}
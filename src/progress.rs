@@ -0,0 +1,157 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+};
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tower_lsp::{
+    lsp_types::{
+        notification::Progress as ProgressNotification, request::WorkDoneProgressCreate,
+        NumberOrString, ProgressParams, ProgressParamsValue, WorkDoneProgress,
+        WorkDoneProgressBegin, WorkDoneProgressCreateParams, WorkDoneProgressEnd,
+        WorkDoneProgressReport,
+    },
+    Client,
+};
+
+/// Progresses currently reported to the client, the client can cancel them
+/// through `window/workDoneProgress/cancel`.
+#[derive(Debug, Default)]
+pub struct ProgressTokens {
+    supported: AtomicBool,
+    counter: AtomicU32,
+    active: DashMap<String, Arc<AtomicBool>>,
+}
+
+impl ProgressTokens {
+    pub fn set_supported(&self, supported: bool) {
+        self.supported.store(supported, Ordering::Relaxed);
+    }
+
+    pub fn cancel(&self, token: &NumberOrString) {
+        if let Some(cancelled) = self.active.get(&token_key(token)) {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CancelParams {
+    pub token: NumberOrString,
+}
+
+/// Work done progress of a long operation, does nothing when the client
+/// doesn't support it.
+pub struct Progress {
+    client: Client,
+    tokens: Arc<ProgressTokens>,
+    token: Option<NumberOrString>,
+    cancelled: Arc<AtomicBool>,
+    total: usize,
+    percentage: u32,
+}
+
+impl Progress {
+    pub async fn begin(
+        client: &Client,
+        tokens: &Arc<ProgressTokens>,
+        title: &str,
+        total: usize,
+    ) -> Self {
+        let mut progress = Self {
+            client: client.clone(),
+            tokens: tokens.clone(),
+            token: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            total,
+            percentage: 0,
+        };
+
+        if !tokens.supported.load(Ordering::Relaxed) {
+            return progress;
+        }
+
+        let id = tokens.counter.fetch_add(1, Ordering::Relaxed);
+        let token = NumberOrString::String(format!("icelang/progress/{}", id));
+        let params = WorkDoneProgressCreateParams {
+            token: token.clone(),
+        };
+
+        if client
+            .send_request::<WorkDoneProgressCreate>(params)
+            .await
+            .is_err()
+        {
+            return progress;
+        }
+
+        tokens
+            .active
+            .insert(token_key(&token), progress.cancelled.clone());
+        progress.token = Some(token);
+        progress
+            .notify(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_owned(),
+                cancellable: Some(true),
+                message: Some(format!("0/{} files", total)),
+                percentage: Some(0),
+            }))
+            .await;
+
+        progress
+    }
+
+    /// Reports the number of processed files, only sent when the percentage
+    /// changes to avoid flooding the client.
+    pub async fn report(&mut self, done: usize) {
+        let percentage = (done * 100 / self.total.max(1)) as u32;
+
+        if percentage == self.percentage {
+            return;
+        }
+
+        self.percentage = percentage;
+        self.notify(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(true),
+            message: Some(format!("{}/{} files", done, self.total)),
+            percentage: Some(percentage),
+        }))
+        .await;
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub async fn end(self, message: String) {
+        self.notify(WorkDoneProgress::End(WorkDoneProgressEnd {
+            message: Some(message),
+        }))
+        .await;
+
+        if let Some(token) = &self.token {
+            self.tokens.active.remove(&token_key(token));
+        }
+    }
+
+    async fn notify(&self, value: WorkDoneProgress) {
+        if let Some(token) = &self.token {
+            let params = ProgressParams {
+                token: token.clone(),
+                value: ProgressParamsValue::WorkDone(value),
+            };
+
+            self.client
+                .send_notification::<ProgressNotification>(params)
+                .await;
+        }
+    }
+}
+
+fn token_key(token: &NumberOrString) -> String {
+    match token {
+        NumberOrString::Number(number) => number.to_string(),
+        NumberOrString::String(string) => string.clone(),
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use serde_json::Value;
+use tower_lsp::lsp_types::Url;
+
+use crate::{config::Project, formatter::FormatOptions, lint::LintConfig};
+
+pub const SECTION: &str = "icelang";
+
+/// Editor settings of the `icelang` section, read through
+/// `workspace/configuration` for each workspace folder.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub lints: LintConfig,
+    pub inlay_hints: InlayHintSettings,
+    pub format: Option<FormatOptions>,
+    /// Directories searched for the modules which aren't found next to the
+    /// importing file.
+    pub module_paths: Vec<PathBuf>,
+    /// Reserved for running the files, only checked for existence for now.
+    pub interpreter_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InlayHintSettings {
+    pub parameter_names: bool,
+}
+
+impl Settings {
+    /// Parses the settings, relative paths are resolved against the folder.
+    /// A missing section gives the default settings.
+    pub fn parse(value: Value, folder: Option<&Path>) -> Result<Self, String> {
+        if value.is_null() {
+            return Ok(Self::default());
+        }
+
+        let mut settings: Self = serde_json::from_value(value).map_err(|err| err.to_string())?;
+
+        if let Some(folder) = folder {
+            for path in &mut settings.module_paths {
+                *path = folder.join(&path);
+            }
+
+            if let Some(path) = &mut settings.interpreter_path {
+                if path.components().count() > 1 {
+                    *path = folder.join(&path);
+                }
+            }
+        }
+
+        Ok(settings)
+    }
+
+    /// Returns the problems of settings which are valid but can't be used.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        for path in &self.module_paths {
+            if !path.is_dir() {
+                problems.push(format!(
+                    "module path '{}' is not a directory",
+                    path.display()
+                ));
+            }
+        }
+
+        // a bare name is looked up in the PATH by the editor
+        if let Some(path) = &self.interpreter_path {
+            if path.components().count() > 1 && !path.is_file() {
+                problems.push(format!(
+                    "interpreter path '{}' does not exist",
+                    path.display()
+                ));
+            }
+        }
+
+        problems
+    }
+}
+
+/// Settings of each workspace folder, files outside of the folders use the
+/// global settings. The project files found so far take precedence over the
+/// editor settings of the files below them.
+#[derive(Debug, Clone, Default)]
+pub struct SettingsMap {
+    pub global: Settings,
+    pub folders: Vec<(PathBuf, Settings)>,
+    pub projects: Vec<Arc<Project>>,
+}
+
+impl SettingsMap {
+    pub fn get(&self, uri: &Url) -> Settings {
+        match uri.to_file_path() {
+            Ok(path) => self.get_by_path(&path),
+            Err(_) => self.global.clone(),
+        }
+    }
+
+    pub fn get_by_path(&self, path: &Path) -> Settings {
+        let mut settings = self.get_editor_settings(path).clone();
+
+        if let Some(project) = self.get_project(path) {
+            project.apply(&mut settings);
+        }
+
+        settings
+    }
+
+    /// Returns the settings of the workspace folder, without the project.
+    pub fn get_editor_settings(&self, path: &Path) -> &Settings {
+        self.folders
+            .iter()
+            .filter(|(folder, _)| path.starts_with(folder))
+            .max_by_key(|(folder, _)| folder.components().count())
+            .map_or(&self.global, |(_, settings)| settings)
+    }
+
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        self.get_project(path)
+            .is_some_and(|project| project.is_ignored(path))
+    }
+
+    /// Returns the closest project containing the file.
+    pub fn get_project(&self, path: &Path) -> Option<&Arc<Project>> {
+        self.projects
+            .iter()
+            .filter(|project| path.starts_with(project.directory()))
+            .max_by_key(|project| project.directory().components().count())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn parse_settings() {
+        let value = json!({
+            "module_paths": ["lib"],
+            "inlay_hints": { "parameter_names": true },
+            "format": { "indent_width": 2 },
+        });
+        let settings = Settings::parse(value, Some(Path::new("/project"))).unwrap();
+
+        assert_eq!(settings.module_paths, [PathBuf::from("/project/lib")]);
+        assert!(settings.inlay_hints.parameter_names);
+        assert_eq!(settings.format.map(|format| format.indent_width), Some(2));
+        assert!(!Settings::default().inlay_hints.parameter_names);
+        assert!(Settings::parse(Value::Null, None).is_ok());
+    }
+
+    #[test]
+    fn parse_unknown_settings() {
+        let unknown = [
+            json!({ "modulePaths": ["lib"] }),
+            json!({ "inlay_hints": { "parameterNames": false } }),
+            json!({ "format": { "indent_size": 2 } }),
+        ];
+
+        for value in unknown {
+            let err = Settings::parse(value, None).unwrap_err();
+
+            assert!(err.starts_with("unknown field"), "{}", err);
+        }
+    }
+}
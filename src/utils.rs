@@ -6,6 +6,10 @@ pub fn point_to_position(point: Point) -> Position {
     Position::new(point.row as u32, point.column as u32)
 }
 
+pub fn position_to_point(position: Position) -> Point {
+    Point::new(position.line as usize, position.character as usize)
+}
+
 pub fn get_node_range(node: &Node) -> Range {
     let start = point_to_position(node.start_position());
     let end = point_to_position(node.end_position());
@@ -22,3 +26,40 @@ pub fn tsrange_to_lsprange(range: tree_sitter::Range) -> Range {
 lazy_static! {
     pub static ref NIL_RANGE: Range = Range::new(Position::new(0, 0), Position::new(0, 0));
 }
+
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            let value = (row[j + 1] + 1).min(row[j] + 1).min(prev + cost);
+
+            prev = row[j + 1];
+            row[j + 1] = value;
+        }
+    }
+
+    row[b.len()]
+}
+
+pub fn get_suggestions<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut matches: Vec<(usize, &str)> = candidates
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+
+    matches.sort();
+    matches.dedup();
+    matches.truncate(3);
+    matches
+        .into_iter()
+        .map(|(_, candidate)| candidate.to_owned())
+        .collect()
+}
@@ -0,0 +1,73 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tower_lsp::lsp_types::Url;
+
+use crate::{
+    document::{Document, PositionEncoding, Snapshot},
+    module::{get_exports, Exports},
+    settings::Settings,
+};
+
+pub const EXTENSION: &str = "ic";
+
+/// Collects the icelang files of a directory recursively, skipping hidden
+/// directories, paths that aren't directories are collected as is.
+pub fn collect_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        return files.push(path.to_owned());
+    }
+
+    let Ok(entries) = fs::read_dir(path) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+
+    entries.sort();
+
+    for entry in entries {
+        let hidden = entry
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+
+        if entry.is_dir() && !hidden {
+            collect_files(&entry, files);
+        } else if is_source_file(&entry) {
+            files.push(entry);
+        }
+    }
+}
+
+pub fn is_source_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == EXTENSION)
+}
+
+/// Reads and analyzes a file which isn't opened in the editor.
+pub fn load_file(
+    path: &Path,
+    encoding: PositionEncoding,
+    settings: &Settings,
+    exports: &dyn Fn(&Url) -> Option<Exports>,
+) -> Option<Snapshot> {
+    let content = fs::read_to_string(path).ok()?;
+    let document = Document::new(0, content, encoding)?;
+    let uri = Url::from_file_path(path).ok()?;
+
+    Some(Snapshot::analyze(
+        Arc::new(document),
+        &uri,
+        settings,
+        exports,
+    ))
+}
+
+/// Reads the exports of a module which isn't indexed, without checking it.
+pub fn load_exports(uri: &Url) -> Option<Exports> {
+    let content = fs::read_to_string(uri.to_file_path().ok()?).ok()?;
+    let document = Document::new(0, content, PositionEncoding::default())?;
+
+    Some(get_exports(document.content.as_bytes(), &document.tree))
+}
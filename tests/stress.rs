@@ -0,0 +1,131 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::{future::join_all, StreamExt};
+use icelang_ls::backend::Backend;
+use serde_json::{json, Value};
+use tower::{Service, ServiceExt};
+use tower_lsp::{jsonrpc::Request, LspService};
+
+const URI: &str = "file:///stress.ic";
+const EDITS: i32 = 200;
+
+async fn call(service: &mut LspService<Backend>, request: Request) -> Option<Value> {
+    let response = service.ready().await.unwrap().call(request).await.unwrap();
+    let (_, result) = response?.into_parts();
+
+    Some(result.expect("request failed"))
+}
+
+fn request(id: i64, method: &'static str, params: Value) -> Request {
+    Request::build(method).id(id).params(params).finish()
+}
+
+fn notification(method: &'static str, params: Value) -> Request {
+    Request::build(method).params(params).finish()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_requests_during_edits() {
+    let (mut service, socket) = LspService::new(Backend::new);
+    let published = Arc::new(Mutex::new(Vec::new()));
+    let sink = published.clone();
+
+    tokio::spawn(socket.for_each(move |message| {
+        if message.method() == "textDocument/publishDiagnostics" {
+            sink.lock()
+                .unwrap()
+                .push(message.params().cloned().unwrap());
+        }
+
+        async {}
+    }));
+
+    let params = json!({ "capabilities": {} });
+
+    call(&mut service, request(0, "initialize", params)).await;
+    call(&mut service, notification("initialized", json!({}))).await;
+
+    let text = "set v1 = 1\n";
+    let params = json!({
+        "textDocument": { "uri": URI, "languageId": "icelang", "version": 1, "text": text }
+    });
+
+    call(&mut service, notification("textDocument/didOpen", params)).await;
+
+    let mut responses = Vec::new();
+
+    for version in 2..=EDITS {
+        let line = version - 1;
+        let change = json!({
+            "textDocument": { "uri": URI, "version": version },
+            "contentChanges": [{
+                "range": {
+                    "start": { "line": line, "character": 0 },
+                    "end": { "line": line, "character": 0 }
+                },
+                "text": format!("set v{} = v{} + 1\n", version, version - 1)
+            }]
+        });
+
+        call(&mut service, notification("textDocument/didChange", change)).await;
+
+        let id = version as i64 * 3;
+        let position = json!({ "line": line, "character": 4 });
+        let range = json!({ "start": position, "end": position });
+        let requests = [
+            request(
+                id,
+                "textDocument/completion",
+                json!({ "textDocument": { "uri": URI }, "position": position }),
+            ),
+            request(
+                id + 1,
+                "textDocument/codeAction",
+                json!({
+                    "textDocument": { "uri": URI },
+                    "range": range,
+                    "context": { "diagnostics": [] }
+                }),
+            ),
+            request(
+                id + 2,
+                "textDocument/formatting",
+                json!({
+                    "textDocument": { "uri": URI },
+                    "options": { "tabSize": 4, "insertSpaces": true }
+                }),
+            ),
+        ];
+
+        for request in requests {
+            let response = service.ready().await.unwrap().call(request);
+
+            responses.push(tokio::spawn(response));
+        }
+    }
+
+    for response in join_all(responses).await {
+        let response = response.unwrap().unwrap().expect("missing response");
+
+        assert!(response.is_ok(), "{:?}", response);
+    }
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let published = published.lock().unwrap();
+    let versions: Vec<_> = published
+        .iter()
+        .map(|params| params["version"].as_i64().unwrap())
+        .collect();
+
+    assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(versions.last(), Some(&(EDITS as i64)));
+
+    let diagnostics = published.last().unwrap()["diagnostics"].as_array().unwrap();
+    let messages: Vec<_> = diagnostics.iter().map(|d| &d["message"]).collect();
+
+    assert_eq!(messages, [&json!(format!("'v{}' is never used", EDITS))]);
+}
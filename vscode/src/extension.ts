@@ -1,14 +1,45 @@
-import { workspace, ExtensionContext, commands, window } from "vscode";
+import {
+  workspace,
+  ExtensionContext,
+  commands,
+  window,
+  WorkspaceEdit,
+} from "vscode";
 import {
   Executable,
   LanguageClient,
   LanguageClientOptions,
   ServerOptions,
+  TextEdit,
 } from "vscode-languageclient/node";
 
 let client: LanguageClient;
 
-export function activate(_: ExtensionContext) {
+async function moveItem(direction: "up" | "down") {
+  const editor = window.activeTextEditor;
+
+  if (!editor || editor.document.languageId !== "icelang") {
+    return;
+  }
+
+  const edits = await client.sendRequest<TextEdit[]>("icelang/moveItem", {
+    textDocument: { uri: editor.document.uri.toString() },
+    position: client.code2ProtocolConverter.asPosition(editor.selection.active),
+    direction,
+  });
+
+  if (edits.length === 0) {
+    return;
+  }
+
+  const workspaceEdit = new WorkspaceEdit();
+  const textEdits = await client.protocol2CodeConverter.asTextEdits(edits);
+
+  workspaceEdit.set(editor.document.uri, textEdits);
+  await workspace.applyEdit(workspaceEdit);
+}
+
+export function activate(context: ExtensionContext) {
   const run: Executable = { command: "icelang-ls" };
 
   const serverOptions: ServerOptions = {
@@ -18,9 +49,6 @@ export function activate(_: ExtensionContext) {
 
   const clientOptions: LanguageClientOptions = {
     documentSelector: [{ scheme: "file", language: "icelang" }],
-    synchronize: {
-      fileEvents: workspace.createFileSystemWatcher("**/.clientrc"),
-    },
   };
 
   client = new LanguageClient(
@@ -30,6 +58,11 @@ export function activate(_: ExtensionContext) {
     clientOptions
   );
 
+  context.subscriptions.push(
+    commands.registerCommand("icelang.moveItemUp", () => moveItem("up")),
+    commands.registerCommand("icelang.moveItemDown", () => moveItem("down"))
+  );
+
   client.start();
 }
 